@@ -0,0 +1,39 @@
+//! snake_server/src/log_mod.rs
+//! The smallest logger for the log macros: one line on stderr with the seconds since the start and the level.
+//! The level is from the environment variable RUST_LOG like error, warn, info or debug, the default is info.
+
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+struct StderrLogger {
+    start: OnceLock<Instant>,
+}
+
+static LOGGER: StderrLogger = StderrLogger { start: OnceLock::new() };
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let seconds = self.start.get_or_init(Instant::now).elapsed().as_secs_f64();
+            eprintln!("{seconds:10.3} {:5} {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// call once at the start of main, the messages before it are lost
+pub fn init() {
+    let level = std::env::var("RUST_LOG").ok().and_then(|level| level.parse().ok()).unwrap_or(LevelFilter::Info);
+    LOGGER.start.get_or_init(Instant::now);
+    // only the first logger is set, a second init is ignored
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
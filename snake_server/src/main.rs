@@ -0,0 +1,201 @@
+//! snake_server/src/main.rs
+//! Authoritative server for the online game of snake_bevy_wasm.
+//! The game runs here, the browsers send only their turns over WebSocket and render the snapshots.
+//! Run it on localhost with `cargo run -p snake_server` and open the game with ?server=ws://localhost:9001&room=lobby
+//! The first argument is the address to listen on, the default is 127.0.0.1:9001
+
+use std::collections::HashMap;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+
+// the game rules and the protocol are the same source files as in the wasm client
+#[allow(dead_code)]
+#[path = "../../src/net_protocol_mod.rs"]
+mod net_protocol_mod;
+mod log_mod;
+mod room_mod;
+#[allow(dead_code)]
+#[path = "../../src/snake_game_mod.rs"]
+mod snake_game_mod;
+mod websocket_mod;
+
+use net_protocol_mod::ClientMessage;
+use room_mod::{ConnectionId, Outgoing, Rooms};
+use snake_game_mod::DEFAULT_STEP_DURATION;
+use websocket_mod::Frame;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:9001";
+/// a client that does not read its messages is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+/// a client with this many messages not yet written falls behind and is disconnected
+const WRITE_QUEUE_CAPACITY: usize = 64;
+
+/// the reader threads send everything to the game loop, only the game loop changes the rooms
+enum ServerEvent {
+    Connected(ConnectionId, ConnectionWriter),
+    Message(ConnectionId, ClientMessage),
+    Disconnected(ConnectionId),
+}
+
+/// what the writer thread of a connection writes
+enum WriterMessage {
+    Text(String),
+    /// the answer to a ping from the reader thread
+    Pong(Vec<u8>),
+}
+
+/// The game loop only queues the messages, the writer thread of the connection writes them.
+/// So a slow client never blocks the steps of the games.
+/// Only the writer thread writes to the socket, so the frames never interleave.
+struct ConnectionWriter {
+    sender: SyncSender<WriterMessage>,
+    /// to disconnect a client that falls behind
+    stream: TcpStream,
+}
+
+fn main() {
+    log_mod::init();
+    let address = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+    let listener = TcpListener::bind(&address).unwrap_or_else(|error| panic!("Could not listen on {address}: {error}"));
+    info!("snake_server listens on ws://{address}");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || accept_connections(listener, sender));
+    run_game_loop(receiver);
+}
+
+/// every connection has its own reader thread and writer thread
+fn accept_connections(listener: TcpListener, sender: Sender<ServerEvent>) {
+    for (connection_id, stream) in (1..).zip(listener.incoming()) {
+        match stream {
+            Ok(stream) => {
+                let sender = sender.clone();
+                thread::spawn(move || read_connection(connection_id, stream, sender));
+            }
+            Err(error) => warn!("Could not accept a connection: {error}"),
+        }
+    }
+}
+
+/// handshake, then every text frame is one ClientMessage until the connection is closed
+fn read_connection(connection_id: ConnectionId, mut stream: TcpStream, sender: Sender<ServerEvent>) {
+    if let Err(error) = websocket_mod::accept_handshake(&mut stream) {
+        warn!("connection {connection_id}: handshake failed: {error}");
+        return;
+    }
+    let (Ok(writer_stream), Ok(shutdown_stream)) = (stream.try_clone(), stream.try_clone()) else {
+        return;
+    };
+    // the snapshots are small and must not wait for more data
+    let _ = writer_stream.set_nodelay(true);
+    let _ = writer_stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    let (message_sender, message_receiver) = mpsc::sync_channel(WRITE_QUEUE_CAPACITY);
+    thread::spawn(move || write_connection(connection_id, writer_stream, message_receiver));
+    let pong_sender = message_sender.clone();
+    let writer = ConnectionWriter {
+        sender: message_sender,
+        stream: shutdown_stream,
+    };
+    if sender.send(ServerEvent::Connected(connection_id, writer)).is_err() {
+        return;
+    }
+    // a close frame or an error ends the connection
+    loop {
+        match websocket_mod::read_frame(&mut stream) {
+            Ok(Frame::Text(text)) => match ron::from_str::<ClientMessage>(&text) {
+                Ok(message) => {
+                    if sender.send(ServerEvent::Message(connection_id, message)).is_err() {
+                        return;
+                    }
+                }
+                Err(error) => warn!("connection {connection_id}: could not parse {text}: {error}"),
+            },
+            // a full queue is a client that falls behind, the game loop disconnects it
+            Ok(Frame::Ping(payload)) => {
+                let _ = pong_sender.try_send(WriterMessage::Pong(payload));
+            }
+            Ok(Frame::Close) | Err(_) => break,
+        }
+    }
+    // without the last sender of the reader the writer thread ends after the game loop removes its writer
+    drop(pong_sender);
+    let _ = sender.send(ServerEvent::Disconnected(connection_id));
+}
+
+/// Write the queued messages until the game loop and the reader thread drop their senders, then the close frame.
+/// A failed write closes the connection, then the reader thread sends Disconnected.
+fn write_connection(connection_id: ConnectionId, mut stream: TcpStream, receiver: Receiver<WriterMessage>) {
+    for message in receiver {
+        let result = match message {
+            WriterMessage::Text(text) => websocket_mod::write_text(&mut stream, &text),
+            WriterMessage::Pong(payload) => websocket_mod::write_pong(&mut stream, &payload),
+        };
+        if let Err(error) = result {
+            warn!("connection {connection_id}: write failed: {error}");
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+    let _ = websocket_mod::write_close(&mut stream);
+}
+
+/// The fixed step of all the games and the messages of the clients in between.
+/// A step is never delayed by many messages, it has priority.
+fn run_game_loop(receiver: Receiver<ServerEvent>) {
+    let mut writers: HashMap<ConnectionId, ConnectionWriter> = HashMap::new();
+    let mut rooms = Rooms::new(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis() as u64));
+    // the online game has no difficulty curve, the same speed for both players as at the start of a local game
+    let step_duration = DEFAULT_STEP_DURATION;
+    let step = Duration::from_secs_f64(step_duration);
+    let mut next_step = Instant::now() + step;
+    loop {
+        let now = Instant::now();
+        if now >= next_step {
+            next_step += step;
+            send_all(&mut writers, rooms.step());
+            continue;
+        }
+        let outgoing = match receiver.recv_timeout(next_step - now) {
+            Ok(ServerEvent::Connected(connection_id, writer)) => {
+                info!("connection {connection_id} connected");
+                writers.insert(connection_id, writer);
+                vec![]
+            }
+            Ok(ServerEvent::Message(connection_id, message)) => rooms.handle_message(connection_id, message, step_duration),
+            Ok(ServerEvent::Disconnected(connection_id)) => {
+                info!("connection {connection_id} disconnected");
+                // without the sender the writer thread writes the close frame and ends
+                writers.remove(&connection_id);
+                rooms.handle_disconnect(connection_id)
+            }
+            Err(RecvTimeoutError::Timeout) => vec![],
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        send_all(&mut writers, outgoing);
+    }
+}
+
+/// a client that falls behind is disconnected, then the reader thread sends Disconnected
+fn send_all(writers: &mut HashMap<ConnectionId, ConnectionWriter>, outgoing: Outgoing) {
+    for (connection_id, message) in outgoing {
+        let Some(writer) = writers.get(&connection_id) else {
+            continue;
+        };
+        let text = match ron::to_string(&message) {
+            Ok(text) => text,
+            Err(error) => {
+                error!("Could not serialize the message: {error}");
+                continue;
+            }
+        };
+        // a closed writer thread has already shut the connection down
+        if let Err(TrySendError::Full(_)) = writer.sender.try_send(WriterMessage::Text(text)) {
+            warn!("connection {connection_id}: too many messages waiting, disconnected");
+            let _ = writer.stream.shutdown(Shutdown::Both);
+            writers.remove(&connection_id);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! snake_server/src/room_mod.rs
+//! The lobby and the rooms of the online game, without any network code.
+//! Every function returns the messages for the connections, main.rs sends them.
+//! A room waits until it is full, then the game starts and SnakeGame::step() runs on the server only.
+
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::net_protocol_mod::{ClientMessage, PROTOCOL_VERSION, ROOM_PLAYER_COUNT, ServerMessage};
+use crate::snake_game_mod::{DirectionQueue, GameRules, Level, SnakeGame, SplitMix64};
+
+pub type ConnectionId = u64;
+/// the message for one connection
+pub type Outgoing = Vec<(ConnectionId, ServerMessage)>;
+
+/// a room name longer than this is cut
+const MAX_ROOM_NAME_LENGTH: usize = 32;
+
+/// one game for ROOM_PLAYER_COUNT players
+struct Room {
+    /// the connection of every player by player index, None is a free place
+    players: Vec<Option<ConnectionId>>,
+    /// None while waiting for players
+    game: Option<SnakeGame>,
+    /// the turns of every player wait here for the next step, like the SnakeHead queue in the client
+    direction_queues: Vec<DirectionQueue>,
+}
+
+pub struct Rooms {
+    rooms: HashMap<String, Room>,
+    /// the room of every connection that joined one
+    connection_rooms: HashMap<ConnectionId, String>,
+    /// the same SplitMix64 as GameRng in the client
+    rng: SplitMix64,
+}
+
+impl Room {
+    fn new() -> Room {
+        Room {
+            players: vec![None; ROOM_PLAYER_COUNT],
+            game: None,
+            direction_queues: vec![DirectionQueue::default(); ROOM_PLAYER_COUNT],
+        }
+    }
+
+    fn connections(&self) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.players.iter().flatten().copied()
+    }
+
+    fn player_index(&self, connection_id: ConnectionId) -> Option<usize> {
+        self.players.iter().position(|player| *player == Some(connection_id))
+    }
+
+    fn broadcast(&self, message: ServerMessage) -> Outgoing {
+        self.connections().map(|connection_id| (connection_id, message.clone())).collect()
+    }
+
+    fn lobby_message(&self) -> ServerMessage {
+        ServerMessage::Lobby {
+            players: self.connections().count(),
+            needed: ROOM_PLAYER_COUNT,
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.game.as_ref().is_some_and(|game| game.is_over())
+    }
+}
+
+impl Rooms {
+    pub fn new(seed: u64) -> Rooms {
+        Rooms {
+            rooms: HashMap::new(),
+            connection_rooms: HashMap::new(),
+            rng: SplitMix64::new(seed),
+        }
+    }
+
+    pub fn handle_message(&mut self, connection_id: ConnectionId, message: ClientMessage, step_duration: f64) -> Outgoing {
+        match message {
+            ClientMessage::Join { room, version } => self.join(connection_id, &room, version, step_duration),
+            ClientMessage::Turn(direction) => {
+                let Some(room) = self.connection_rooms.get(&connection_id).and_then(|room_name| self.rooms.get_mut(room_name)) else {
+                    return vec![(connection_id, ServerMessage::Error("Join a room first.".to_string()))];
+                };
+                if let (Some(player_index), Some(game)) = (room.player_index(connection_id), &room.game) {
+                    room.direction_queues[player_index].push(direction, &game.snakes[player_index].direction);
+                }
+                vec![]
+            }
+            ClientMessage::Leave => self.leave(connection_id),
+        }
+    }
+
+    /// the closed connection leaves the room
+    pub fn handle_disconnect(&mut self, connection_id: ConnectionId) -> Outgoing {
+        self.leave(connection_id)
+    }
+
+    /// One step of every running game, all the players get the snapshot.
+    /// The rooms with a finished game are removed, the players can join again.
+    pub fn step(&mut self) -> Outgoing {
+        let mut outgoing = vec![];
+        for room in self.rooms.values_mut() {
+            let Some(game) = &mut room.game else {
+                continue;
+            };
+            let inputs: Vec<_> = room.direction_queues.iter_mut().map(|direction_queue| direction_queue.pop()).collect();
+            let events = game.step(&inputs, &mut self.rng);
+            let snapshot = ServerMessage::Snapshot {
+                tick: game.moves,
+                game: game.clone(),
+                events,
+            };
+            outgoing.extend(room.broadcast(snapshot));
+        }
+        self.remove_finished_rooms();
+        outgoing
+    }
+
+    fn join(&mut self, connection_id: ConnectionId, room_name: &str, version: u32, step_duration: f64) -> Outgoing {
+        if version != PROTOCOL_VERSION {
+            return vec![(
+                connection_id,
+                ServerMessage::Error(format!("The server has protocol version {PROTOCOL_VERSION}, the game has {version}.")),
+            )];
+        }
+        // a second join is a change of the room
+        let mut outgoing = self.leave(connection_id);
+        let room_name: String = room_name.trim().chars().take(MAX_ROOM_NAME_LENGTH).collect();
+        let room = self.rooms.entry(room_name.clone()).or_insert_with(Room::new);
+        let Some(player_index) = room.players.iter().position(|player| player.is_none()).filter(|_| room.game.is_none()) else {
+            outgoing.push((connection_id, ServerMessage::Error(format!("The game in room {room_name} already started."))));
+            return outgoing;
+        };
+        room.players[player_index] = Some(connection_id);
+        self.connection_rooms.insert(connection_id, room_name.clone());
+        info!("connection {connection_id} joined room {room_name} as player {}", player_index + 1);
+        outgoing.push((
+            connection_id,
+            ServerMessage::Joined {
+                room: room_name.clone(),
+                player_index,
+            },
+        ));
+        outgoing.extend(room.broadcast(room.lobby_message()));
+        if room.connections().count() == ROOM_PLAYER_COUNT {
+            let rules = GameRules {
+                snake_count: ROOM_PLAYER_COUNT,
+                ..GameRules::default()
+            };
+            // the online game is always on the open board
+            let game = SnakeGame::new(&rules, &Level::default(), &mut self.rng);
+            info!("room {room_name} started");
+            outgoing.extend(room.broadcast(ServerMessage::Started { step_duration, game: game.clone() }));
+            room.game = Some(game);
+        }
+        outgoing
+    }
+
+    /// In the lobby the place is free again. In a running game the snake of the player dies,
+    /// the other player gets the last snapshot and wins.
+    fn leave(&mut self, connection_id: ConnectionId) -> Outgoing {
+        let Some(room_name) = self.connection_rooms.remove(&connection_id) else {
+            return vec![];
+        };
+        let Some(room) = self.rooms.get_mut(&room_name) else {
+            return vec![];
+        };
+        let Some(player_index) = room.player_index(connection_id) else {
+            return vec![];
+        };
+        room.players[player_index] = None;
+        info!("connection {connection_id} left room {room_name}");
+        let message = match &mut room.game {
+            Some(game) => {
+                let events = game.remove_player(player_index);
+                ServerMessage::Snapshot {
+                    tick: game.moves,
+                    game: game.clone(),
+                    events,
+                }
+            }
+            None => room.lobby_message(),
+        };
+        let outgoing = room.broadcast(message);
+        if room.connections().count() == 0 {
+            self.rooms.remove(&room_name);
+        }
+        self.remove_finished_rooms();
+        outgoing
+    }
+
+    fn remove_finished_rooms(&mut self) {
+        let finished: Vec<String> = self.rooms.iter().filter(|(_, room)| room.is_over()).map(|(room_name, _)| room_name.clone()).collect();
+        for room_name in finished {
+            if let Some(room) = self.rooms.remove(&room_name) {
+                info!("room {room_name} is over");
+                for connection_id in room.connections() {
+                    self.connection_rooms.remove(&connection_id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_game_mod::{DeathCause, Direction, GameEvent};
+
+    const STEP_DURATION: f64 = 0.2;
+
+    fn join(rooms: &mut Rooms, connection_id: ConnectionId, room: &str) -> Outgoing {
+        let message = ClientMessage::Join {
+            room: room.to_string(),
+            version: PROTOCOL_VERSION,
+        };
+        rooms.handle_message(connection_id, message, STEP_DURATION)
+    }
+
+    /// the two players are in the room "lobby" and the game started
+    fn started_rooms() -> Rooms {
+        let mut rooms = Rooms::new(1);
+        join(&mut rooms, 1, "lobby");
+        join(&mut rooms, 2, "lobby");
+        rooms
+    }
+
+    #[test]
+    fn join_waits_for_the_second_player_and_starts_the_game() {
+        let mut rooms = Rooms::new(1);
+        let outgoing = join(&mut rooms, 1, " lobby ");
+        assert!(matches!(&outgoing[..], [
+            (1, ServerMessage::Joined { room, player_index: 0 }),
+            (1, ServerMessage::Lobby { players: 1, needed: ROOM_PLAYER_COUNT }),
+        ] if room == "lobby"));
+
+        let outgoing = join(&mut rooms, 2, "lobby");
+        assert!(matches!(outgoing[0], (2, ServerMessage::Joined { player_index: 1, .. })));
+        let started: Vec<ConnectionId> = outgoing
+            .iter()
+            .filter_map(|(connection_id, message)| match message {
+                ServerMessage::Started { step_duration, game } if *step_duration == STEP_DURATION && game.snakes.len() == ROOM_PLAYER_COUNT => Some(*connection_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(started, vec![1, 2]);
+    }
+
+    #[test]
+    fn join_with_another_version_is_an_error() {
+        let mut rooms = Rooms::new(1);
+        let message = ClientMessage::Join {
+            room: "lobby".to_string(),
+            version: PROTOCOL_VERSION + 1,
+        };
+        let outgoing = rooms.handle_message(1, message, STEP_DURATION);
+        assert!(matches!(&outgoing[..], [(1, ServerMessage::Error(error))] if error.contains("protocol version")));
+        assert!(rooms.rooms.is_empty());
+    }
+
+    #[test]
+    fn join_a_started_game_is_an_error() {
+        let mut rooms = started_rooms();
+        let outgoing = join(&mut rooms, 3, "lobby");
+        assert!(matches!(&outgoing[..], [(3, ServerMessage::Error(error))] if error.contains("already started")));
+        assert!(!rooms.connection_rooms.contains_key(&3));
+    }
+
+    #[test]
+    fn turn_before_join_is_an_error() {
+        let mut rooms = Rooms::new(1);
+        let outgoing = rooms.handle_message(1, ClientMessage::Turn(Direction::Left), STEP_DURATION);
+        assert!(matches!(&outgoing[..], [(1, ServerMessage::Error(_))]));
+    }
+
+    #[test]
+    fn leave_in_the_lobby_frees_the_place() {
+        let mut rooms = Rooms::new(1);
+        join(&mut rooms, 1, "lobby");
+        assert!(rooms.handle_disconnect(1).is_empty());
+        assert!(rooms.rooms.is_empty());
+        assert!(rooms.connection_rooms.is_empty());
+    }
+
+    #[test]
+    fn leave_during_the_game_is_a_win_for_the_other_player() {
+        let mut rooms = started_rooms();
+        let outgoing = rooms.handle_message(1, ClientMessage::Leave, STEP_DURATION);
+        let [(2, ServerMessage::Snapshot { game, events, .. })] = &outgoing[..] else {
+            panic!("expected one snapshot for the other player, got {outgoing:?}");
+        };
+        assert!(game.is_over());
+        assert_eq!(game.winner(), Some(1));
+        assert_eq!(
+            events,
+            &vec![GameEvent::Died {
+                snake_index: 0,
+                cause: DeathCause::Disconnected,
+            }]
+        );
+        // the finished room is removed, the other player can join again
+        assert!(rooms.rooms.is_empty());
+        assert!(rooms.connection_rooms.is_empty());
+        assert!(matches!(join(&mut rooms, 2, "lobby")[0], (2, ServerMessage::Joined { player_index: 0, .. })));
+    }
+
+    #[test]
+    fn step_sends_the_snapshot_to_every_player() {
+        let mut rooms = started_rooms();
+        // the player waiting in another room gets nothing
+        join(&mut rooms, 3, "other");
+        let outgoing = rooms.step();
+        assert_eq!(outgoing.len(), ROOM_PLAYER_COUNT);
+        for (connection_id, (expected_id, message)) in [1, 2].into_iter().zip(&outgoing) {
+            assert_eq!(connection_id, *expected_id);
+            assert!(matches!(message, ServerMessage::Snapshot { tick: 1, game, .. } if game.moves == 1));
+        }
+    }
+
+    #[test]
+    fn step_applies_the_queued_turn() {
+        let mut rooms = started_rooms();
+        let direction = rooms.rooms["lobby"].game.as_ref().map(|game| game.snakes[0].direction.clone()).unwrap();
+        let turn = match direction {
+            Direction::Up | Direction::Down => Direction::Left,
+            Direction::Left | Direction::Right => Direction::Up,
+        };
+        rooms.handle_message(1, ClientMessage::Turn(turn.clone()), STEP_DURATION);
+        let outgoing = rooms.step();
+        assert!(matches!(&outgoing[0].1, ServerMessage::Snapshot { game, .. } if game.snakes[0].direction == turn));
+    }
+
+    #[test]
+    fn finished_rooms_are_removed() {
+        let mut rooms = started_rooms();
+        // without turns the snakes hit a wall or each other
+        for _ in 0..1000 {
+            if rooms.rooms.is_empty() {
+                break;
+            }
+            rooms.step();
+        }
+        assert!(rooms.rooms.is_empty());
+        assert!(rooms.connection_rooms.is_empty());
+    }
+}
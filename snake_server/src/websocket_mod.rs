@@ -0,0 +1,299 @@
+//! snake_server/src/websocket_mod.rs
+//! The minimal WebSocket protocol RFC 6455 over std::net::TcpStream, only what the browser needs for the game.
+//! The functions take any Read and Write, so the tests run without a socket.
+//! The handshake, unfragmented text frames, ping, pong and close.
+//! SHA-1 and base64 are here only for the handshake, so the server has no other dependencies.
+
+use std::io::{self, Read, Write};
+
+/// the constant from RFC 6455 for Sec-WebSocket-Accept
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// a longer request or message is not from the game client
+const MAX_HANDSHAKE_LENGTH: usize = 8 * 1024;
+const MAX_MESSAGE_LENGTH: u64 = 64 * 1024;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// what the reader gets from the client
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Text(String),
+    /// the payload for the pong, the writer thread answers it, so only one thread writes to the socket
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// Read the HTTP upgrade request and answer with 101 Switching Protocols.
+pub fn accept_handshake(stream: &mut (impl Read + Write)) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() > MAX_HANDSHAKE_LENGTH || stream.read(&mut byte)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete handshake"));
+        }
+        request.push(byte[0]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let key = request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .map(|(_, value)| value.trim().to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Sec-WebSocket-Key"))?;
+    let accept = base64_encode(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    )?;
+    stream.flush()
+}
+
+/// Read the next text, ping or close frame, the pongs are ignored.
+/// The frames from the browser are always masked.
+pub fn read_frame(stream: &mut impl Read) -> io::Result<Frame> {
+    loop {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header)?;
+        let is_final = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let is_masked = header[1] & 0x80 != 0;
+        let length = match header[1] & 0x7F {
+            126 => {
+                let mut length = [0u8; 2];
+                stream.read_exact(&mut length)?;
+                u16::from_be_bytes(length) as u64
+            }
+            127 => {
+                let mut length = [0u8; 8];
+                stream.read_exact(&mut length)?;
+                u64::from_be_bytes(length)
+            }
+            length => length as u64,
+        };
+        if !is_final || !is_masked || length > MAX_MESSAGE_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported frame"));
+        }
+        let mut mask = [0u8; 4];
+        stream.read_exact(&mut mask)?;
+        let mut payload = vec![0u8; length as usize];
+        stream.read_exact(&mut payload)?;
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+        match opcode {
+            OPCODE_TEXT => {
+                return String::from_utf8(payload).map(Frame::Text).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "text is not utf8"));
+            }
+            OPCODE_CLOSE => return Ok(Frame::Close),
+            OPCODE_PING => return Ok(Frame::Ping(payload)),
+            // pong and binary frames are ignored
+            _ => {}
+        }
+    }
+}
+
+/// one unmasked text frame, the server never masks
+pub fn write_text(stream: &mut impl Write, text: &str) -> io::Result<()> {
+    write_frame(stream, OPCODE_TEXT, text.as_bytes())
+}
+
+/// the answer to a ping has the same payload
+pub fn write_pong(stream: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    write_frame(stream, OPCODE_PONG, payload)
+}
+
+pub fn write_close(stream: &mut impl Write) -> io::Result<()> {
+    write_frame(stream, OPCODE_CLOSE, &[])
+}
+
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// SHA-1 from FIPS 180-4, it is broken for security but the handshake requires it
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut hash: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for chunk in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in chunk.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = hash;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in hash.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (index, value) in hash.iter().enumerate() {
+        digest[index * 4..index * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+/// standard base64 with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// the bytes from the client are read, the answers of the server are collected
+    struct TestStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl TestStream {
+        fn new(input: Vec<u8>) -> TestStream {
+            TestStream {
+                input: Cursor::new(input),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for TestStream {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buffer)
+        }
+    }
+
+    impl Write for TestStream {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.write(buffer)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// a frame like the browser sends it, masked
+    fn masked_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![];
+        write_frame(&mut frame, opcode, payload).unwrap();
+        let header_length = frame.len() - payload.len();
+        frame[1] |= 0x80;
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut masked = frame[..header_length].to_vec();
+        masked.extend_from_slice(&mask);
+        masked.extend(payload.iter().enumerate().map(|(index, byte)| byte ^ mask[index % 4]));
+        masked
+    }
+
+    #[test]
+    fn handshake_accept_from_rfc_6455() {
+        let accept = base64_encode(&sha1(format!("dGhlIHNhbXBsZSBub25jZQ=={WEBSOCKET_GUID}").as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn handshake_answers_101() {
+        let request = "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+        let mut stream = TestStream::new(request.as_bytes().to_vec());
+        accept_handshake(&mut stream).unwrap();
+        let response = String::from_utf8(stream.output).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn masked_text_frame_round_trip() {
+        let mut stream = TestStream::new(masked_frame(OPCODE_TEXT, b"Turn(Up)"));
+        assert_eq!(read_frame(&mut stream).unwrap(), Frame::Text("Turn(Up)".to_string()));
+    }
+
+    #[test]
+    fn unmasked_text_frame_round_trip() {
+        let mut frame = vec![];
+        write_text(&mut frame, "Hello").unwrap();
+        assert_eq!(frame, [&[0x81, 5][..], b"Hello"].concat());
+        // the server requires the mask from the client
+        assert!(read_frame(&mut TestStream::new(frame)).is_err());
+    }
+
+    #[test]
+    fn frame_with_16_bit_length_round_trip() {
+        let text = "x".repeat(300);
+        let mut frame = vec![];
+        write_text(&mut frame, &text).unwrap();
+        assert_eq!(frame[..4], [0x81, 126, 0x01, 0x2c]);
+        assert_eq!(frame[4..], *text.as_bytes());
+        let mut stream = TestStream::new(masked_frame(OPCODE_TEXT, text.as_bytes()));
+        assert_eq!(read_frame(&mut stream).unwrap(), Frame::Text(text));
+    }
+
+    #[test]
+    fn ping_is_returned_and_not_answered() {
+        let mut input = masked_frame(OPCODE_PONG, b"pong");
+        input.extend(masked_frame(OPCODE_PING, b"ping"));
+        input.extend(masked_frame(OPCODE_CLOSE, &[]));
+        let mut stream = TestStream::new(input);
+        assert_eq!(read_frame(&mut stream).unwrap(), Frame::Ping(b"ping".to_vec()));
+        assert_eq!(read_frame(&mut stream).unwrap(), Frame::Close);
+        assert!(stream.output.is_empty());
+    }
+
+    #[test]
+    fn pong_has_the_payload_of_the_ping() {
+        let mut frame = vec![];
+        write_pong(&mut frame, b"ping").unwrap();
+        assert_eq!(frame, [&[0x8A, 4][..], b"ping"].concat());
+    }
+}
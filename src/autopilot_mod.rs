@@ -0,0 +1,146 @@
+//! src/autopilot_mod.rs
+//! The computer players from snake_controller_mod in the Bevy app.
+//! In the main menu a Hamiltonian snake plays an attract mode game behind the UI.
+//! In the game the AI assist turns the snake when the player does not.
+
+use bevy::prelude::*;
+
+use crate::game_config_mod::GameConfig;
+use crate::game_rng_mod::GameRng;
+use crate::level_mod::Levels;
+use crate::snake_controller_mod::{GreedyAStarController, HamiltonianController, SnakeController};
+use crate::snake_game_mod::{GameRules, Position, SnakeGame};
+use crate::state_in_game_mod::food_color;
+use crate::theme_mod::Theme;
+use crate::web_sys_mod as wsm;
+use crate::{AppState, GameBoardCanvas};
+
+/// seconds between two steps of the attract mode game
+const ATTRACT_STEP_DURATION: f32 = 0.08;
+/// the attract mode is only in the background, the UI must be readable
+const ATTRACT_ALPHA: f32 = 0.35;
+const ATTRACT_Z_LAYER: f32 = 1.0;
+
+/// exists only in the game with AI assist
+#[derive(Resource)]
+pub struct Autopilot(pub Box<dyn SnakeController + Send + Sync>);
+
+/// the computer plays in the main menu
+#[derive(Resource)]
+struct AttractGame {
+    game: SnakeGame,
+    game_rng: GameRng,
+    controller: HamiltonianController,
+    timer: Timer,
+}
+
+// the cells are reused every step, only the missing cells are spawned and the surplus is despawned
+#[derive(Component)]
+struct AttractCell;
+
+pub fn add_autopilot_to_app(app: &mut App) {
+    app.add_systems(OnEnter(AppState::InGame), insert_autopilot);
+    app.add_systems(OnExit(AppState::InGame), remove_autopilot);
+    app.add_systems(OnEnter(AppState::MainMenu), start_attract_game);
+    app.add_systems(OnExit(AppState::MainMenu), stop_attract_game);
+    app.add_systems(Update, step_attract_game.run_if(in_state(AppState::MainMenu)));
+}
+
+fn insert_autopilot(mut commands: Commands, game_config: Res<GameConfig>) {
+    if game_config.ai_assist {
+        commands.insert_resource(Autopilot(Box::new(GreedyAStarController)));
+    }
+}
+
+fn remove_autopilot(mut commands: Commands) {
+    commands.remove_resource::<Autopilot>();
+}
+
+/// the attract mode is always a single player game on the selected level
+fn new_attract_game(game_config: &GameConfig, levels: &Levels) -> AttractGame {
+    let mut game_rng = GameRng::new(wsm::now_milliseconds() as u64);
+    let rules = GameRules {
+        snake_count: 1,
+        ..game_config.rules.clone()
+    };
+    AttractGame {
+        game: SnakeGame::new(&rules, &levels.level_for(game_config), &mut game_rng),
+        game_rng,
+        controller: HamiltonianController::new(game_config.rules.board_width, game_config.rules.board_height),
+        timer: Timer::from_seconds(ATTRACT_STEP_DURATION, TimerMode::Repeating),
+    }
+}
+
+fn start_attract_game(mut commands: Commands, game_config: Res<GameConfig>, levels: Res<Levels>) {
+    commands.insert_resource(new_attract_game(&game_config, &levels));
+}
+
+// the main menu despawns the entities on exit
+fn stop_attract_game(mut commands: Commands) {
+    commands.remove_resource::<AttractGame>();
+}
+
+/// one step of the attract mode game, a new game starts when it is over or when the config or the levels change
+#[allow(clippy::too_many_arguments)]
+fn step_attract_game(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_config: Res<GameConfig>,
+    levels: Res<Levels>,
+    game_board_canvas: Res<GameBoardCanvas>,
+    theme: Res<Theme>,
+    mut attract_game: ResMut<AttractGame>,
+    mut cells: Query<(Entity, &mut Transform, &mut Sprite), With<AttractCell>>,
+) {
+    if game_config.is_changed() || levels.is_changed() {
+        *attract_game = new_attract_game(&game_config, &levels);
+    }
+    if !attract_game.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let attract_game = &mut *attract_game;
+    if attract_game.game.is_over() {
+        *attract_game = new_attract_game(&game_config, &levels);
+    }
+    let direction = attract_game.controller.next_direction(&attract_game.game, 0);
+    attract_game.game.step(&[direction], &mut attract_game.game_rng);
+
+    let game = &attract_game.game;
+    // the colors of the theme like in the game, the body is darker than the head
+    let mut colored_cells: Vec<(&Position, Color)> = vec![];
+    for (snake_index, snake) in game.snakes.iter().enumerate() {
+        let tint = theme.snake_tint(snake_index);
+        colored_cells.push((&snake.head, tint));
+        colored_cells.extend(snake.segments.iter().map(|segment| (&segment.position, tint.darker(0.3))));
+    }
+    colored_cells.extend(game.food.iter().filter(|food| !food.is_waiting).map(|food| (&food.position, food_color(food, game.food_kind(food.kind), &theme))));
+    colored_cells.extend(game.level.walls.iter().map(|wall| (wall, theme.wall_color())));
+
+    let mut cells = cells.iter_mut();
+    for (position, color) in colored_cells {
+        let Some((_, mut transform, mut sprite)) = cells.next() else {
+            spawn_attract_cell(&mut commands, &game_board_canvas, position, color);
+            continue;
+        };
+        transform.translation.x = position.to_bevy_x(&game_board_canvas);
+        transform.translation.y = position.to_bevy_y(&game_board_canvas);
+        sprite.color = color.with_alpha(ATTRACT_ALPHA);
+        sprite.custom_size = Some(attract_cell_size(&game_board_canvas));
+    }
+    for (entity, ..) in cells {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_attract_cell(commands: &mut Commands, game_board_canvas: &GameBoardCanvas, position: &Position, color: Color) {
+    commands.spawn((
+        Sprite::from_color(color.with_alpha(ATTRACT_ALPHA), attract_cell_size(game_board_canvas)),
+        Transform::from_xyz(position.to_bevy_x(game_board_canvas), position.to_bevy_y(game_board_canvas), ATTRACT_Z_LAYER),
+        AttractCell,
+    ));
+}
+
+/// a small gap between the cells
+fn attract_cell_size(game_board_canvas: &GameBoardCanvas) -> Vec2 {
+    Vec2::new(game_board_canvas.sprite_width * 0.9, game_board_canvas.sprite_height * 0.9)
+}
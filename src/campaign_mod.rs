@@ -0,0 +1,147 @@
+//! src/campaign_mod.rs
+//! The campaign is the levels in the order of LEVEL_NAMES, every level has a goal in its file.
+//! The first level is always unlocked, a completed level unlocks the next one.
+//! The completed levels are saved in the browser window.localStorage like the high scores.
+//! When the goal is reached, the transition screen AppState::LevelComplete offers the next level.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_assets_mod::GameAssets;
+use crate::game_config_mod::GameConfig;
+use crate::input_intent_mod::Intent;
+use crate::level_mod::{LEVEL_NAMES, Levels, select_level};
+use crate::snake_game_mod::Level;
+use crate::state_in_game_mod::{GameOver, GameOverCause, SnakeGameRes};
+use crate::theme_mod::Theme;
+use crate::web_sys_mod as wsm;
+use crate::{AppState, GameBoardCanvas};
+
+const STORAGE_KEY: &str = "snake_bevy_wasm_campaign";
+
+#[derive(Resource, Default, Debug, Deserialize, Serialize)]
+pub struct CampaignProgress {
+    /// the names of the completed levels
+    pub completed: Vec<String>,
+}
+
+pub fn add_campaign_to_app(app: &mut App) {
+    app.insert_resource(CampaignProgress::load());
+    app.add_systems(OnEnter(AppState::LevelComplete), on_enter_level_complete);
+    // the game board stays in the background of the transition screen, all is despawned on exit like the dead screen
+    app.add_systems(OnExit(AppState::LevelComplete), crate::state_dead_mod::on_exit_dead);
+    app.add_systems(
+        Update,
+        // the board has the size of the next level before on_enter_in_game
+        (handle_level_complete_input, crate::handle_browser_resize).chain().run_if(in_state(AppState::LevelComplete)),
+    );
+}
+
+impl CampaignProgress {
+    /// a missing or broken storage is a new campaign
+    pub fn load() -> CampaignProgress {
+        wsm::local_storage_get(STORAGE_KEY)
+            .and_then(|text| bevy::asset::ron::from_str::<CampaignProgress>(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        match bevy::asset::ron::to_string(self) {
+            Ok(text) => wsm::local_storage_set(STORAGE_KEY, &text),
+            Err(error) => error!("Could not serialize the campaign progress: {error}"),
+        }
+    }
+
+    pub fn is_completed(&self, level_name: &str) -> bool {
+        self.completed.iter().any(|completed| completed == level_name)
+    }
+
+    /// the first level and every level after a completed one, a level outside of the campaign is always unlocked
+    pub fn is_unlocked(&self, level_name: &str) -> bool {
+        match LEVEL_NAMES.iter().position(|name| *name == level_name) {
+            Some(0) | None => true,
+            Some(index) => self.is_completed(LEVEL_NAMES[index - 1]),
+        }
+    }
+}
+
+/// the level after this one in the campaign, None after the last level
+fn next_level<'a>(levels: &'a Levels, level_name: &str) -> Option<&'a Level> {
+    let index = LEVEL_NAMES.iter().position(|name| *name == level_name)?;
+    LEVEL_NAMES.get(index + 1).and_then(|name| levels.find(name))
+}
+
+/// the level is completed and saved, the screen shows the result and the next level
+#[allow(clippy::too_many_arguments)]
+fn on_enter_level_complete(
+    mut commands: Commands,
+    game_board_canvas: Res<GameBoardCanvas>,
+    game_assets: Res<GameAssets>,
+    theme: Res<Theme>,
+    game: Res<SnakeGameRes>,
+    game_over: Option<Res<GameOver>>,
+    levels: Res<Levels>,
+    mut campaign_progress: ResMut<CampaignProgress>,
+) {
+    let level_name = game.0.level.name.clone();
+    if !campaign_progress.is_completed(&level_name) {
+        campaign_progress.completed.push(level_name.clone());
+        campaign_progress.save();
+    }
+    let winner = match game_over.as_ref().map(|game_over| &game_over.cause) {
+        Some(GameOverCause::GoalReached(snake_index)) if game.0.snakes.len() > 1 => format!("\nPlayer {} reached the goal.", snake_index + 1),
+        _ => String::new(),
+    };
+    let moves = game_over.map_or(game.0.moves, |game_over| game_over.moves);
+    let next = match next_level(&levels, &level_name) {
+        Some(level) => format!("Next level: {}\nPress N to play it, X for the main menu", level.name),
+        None => "The campaign is complete!\nPress N or X for the main menu".to_string(),
+    };
+
+    commands
+        .spawn(Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_children(|level_complete| {
+            level_complete.spawn((
+                Text::new(format!("Level {level_name} complete!")),
+                game_assets.text_font(game_board_canvas.sprite_height * 2.),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.title_color()),
+            ));
+            level_complete.spawn((
+                Text::new(format!("{moves} moves{winner}")),
+                game_assets.text_font(game_board_canvas.sprite_height),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.text_color()),
+            ));
+            level_complete.spawn((
+                Text::new(next),
+                game_assets.text_font(game_board_canvas.sprite_height),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.hint_color()),
+            ));
+        });
+}
+
+/// N plays the next level, after the last level N returns to the main menu like X
+fn handle_level_complete_input(mut intents: EventReader<Intent>, game: Res<SnakeGameRes>, levels: Res<Levels>, mut game_config: ResMut<GameConfig>, mut next_state: ResMut<NextState<AppState>>) {
+    for intent in intents.read() {
+        match intent {
+            Intent::Start => match next_level(&levels, &game.0.level.name) {
+                Some(level) => {
+                    select_level(&mut game_config, Some(level));
+                    next_state.set(AppState::InGame);
+                }
+                None => next_state.set(AppState::MainMenu),
+            },
+            Intent::Quit => next_state.set(AppState::MainMenu),
+            Intent::Turn(..) | Intent::Pause | Intent::Replay | Intent::Menu(_) => {}
+        }
+    }
+}
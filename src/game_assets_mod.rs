@@ -0,0 +1,134 @@
+//! src/game_assets_mod.rs
+//! The images and sounds are loaded once at startup into the resource GameAssets.
+//! The systems clone the handles instead of calling asset_server.load in every step.
+//! The snake is one sprite sheet from the theme, a segment changes only the index in the texture atlas.
+//! The images and sounds of the food kinds are in the config, they are loaded when the config changes.
+//! The sprite sheet and the font are in the theme, they are loaded when the theme changes.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource;
+
+use crate::game_config_mod::{GameConfig, apply_game_config_asset};
+use crate::snake_game_mod::FoodKind;
+use crate::theme_mod::Theme;
+
+/// the tiles of the sprite sheet are squares in one row
+const SNAKE_TILE_SIZE: u32 = 40;
+
+/// the tiles of the sprite sheet from left to right, the value is the atlas index
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SnakePart {
+    Head = 0,
+    Body = 1,
+    Corner = 2,
+    Tail = 3,
+}
+
+#[derive(Resource)]
+pub struct GameAssets {
+    snake_sheet: Handle<Image>,
+    snake_layout: Handle<TextureAtlasLayout>,
+    bird: Handle<Image>,
+    /// None is the default font of Bevy
+    font: Option<Handle<Font>>,
+    pub key_up: Handle<Image>,
+    pub key_down: Handle<Image>,
+    pub key_left: Handle<Image>,
+    pub key_right: Handle<Image>,
+    pub key_n: Handle<Image>,
+    pub bird_chirp: Handle<AudioSource>,
+    pub game_over: Handle<AudioSource>,
+    pub snake_hiss: Handle<AudioSource>,
+    /// the images of the food kinds by file name
+    food_images: HashMap<String, Handle<Image>>,
+    /// the sounds of the food kinds by file name
+    food_sounds: HashMap<String, Handle<AudioSource>>,
+}
+
+pub fn add_game_assets_to_app(app: &mut App) {
+    app.add_systems(Startup, load_game_assets);
+    app.add_systems(Update, load_food_assets.after(apply_game_config_asset).run_if(resource_changed::<GameConfig>));
+}
+
+impl GameAssets {
+    /// the sprite of a part of the snake, the sprite sheet is the same for all parts
+    pub fn snake_sprite(&self, snake_part: SnakePart) -> Sprite {
+        Sprite::from_atlas_image(
+            self.snake_sheet.clone(),
+            TextureAtlas {
+                layout: self.snake_layout.clone(),
+                index: snake_part as usize,
+            },
+        )
+    }
+
+    /// the texts of the menus use the font of the theme
+    pub fn text_font(&self, font_size: f32) -> TextFont {
+        TextFont {
+            font: self.font.clone().unwrap_or_default(),
+            font_size,
+            ..default()
+        }
+    }
+
+    /// the old sprites keep the old sheet until they are spawned again
+    pub fn set_theme(&mut self, theme: &Theme, asset_server: &AssetServer) {
+        self.snake_sheet = asset_server.load(&theme.snake_sheet);
+        self.font = theme.font.as_ref().map(|font| asset_server.load(font));
+    }
+
+    /// a kind from a config that is not loaded yet looks like the classic bird
+    pub fn food_image(&self, food_kind: &FoodKind) -> Handle<Image> {
+        self.food_images.get(&food_kind.image).unwrap_or(&self.bird).clone()
+    }
+
+    pub fn food_sound(&self, food_kind: &FoodKind) -> Handle<AudioSource> {
+        self.food_sounds.get(&food_kind.sound).unwrap_or(&self.bird_chirp).clone()
+    }
+
+    /// all the handles for the loading state
+    pub fn handles(&self) -> Vec<UntypedHandle> {
+        let fonts = self.font.iter().map(|handle| handle.clone().untyped());
+        let images = [&self.snake_sheet, &self.bird, &self.key_up, &self.key_down, &self.key_left, &self.key_right, &self.key_n]
+            .into_iter()
+            .chain(self.food_images.values());
+        let sounds = [&self.bird_chirp, &self.game_over, &self.snake_hiss].into_iter().chain(self.food_sounds.values());
+        images
+            .map(|handle| handle.clone().untyped())
+            .chain(sounds.map(|handle| handle.clone().untyped()))
+            .chain(fonts)
+            .collect()
+    }
+}
+
+/// the theme is still the default until the selected theme is loaded
+fn load_game_assets(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, theme: Res<Theme>) {
+    let snake_layout = TextureAtlasLayout::from_grid(UVec2::splat(SNAKE_TILE_SIZE), 4, 1, None, None);
+    commands.insert_resource(GameAssets {
+        snake_sheet: asset_server.load(&theme.snake_sheet),
+        snake_layout: texture_atlas_layouts.add(snake_layout),
+        bird: asset_server.load("bird.png"),
+        font: theme.font.as_ref().map(|font| asset_server.load(font)),
+        key_up: asset_server.load("key_up.png"),
+        key_down: asset_server.load("key_down.png"),
+        key_left: asset_server.load("key_left.png"),
+        key_right: asset_server.load("key_right.png"),
+        key_n: asset_server.load("key_n.png"),
+        bird_chirp: asset_server.load("bird_chirp.mp3"),
+        game_over: asset_server.load("game_over.mp3"),
+        snake_hiss: asset_server.load("snake_hiss.mp3"),
+        food_images: HashMap::new(),
+        food_sounds: HashMap::new(),
+    });
+}
+
+/// only the new file names are loaded, the old handles stay for the next config change
+pub fn load_food_assets(game_config: Res<GameConfig>, asset_server: Res<AssetServer>, mut game_assets: ResMut<GameAssets>) {
+    let game_assets = &mut *game_assets;
+    for food_kind in &game_config.rules.food_kinds {
+        game_assets.food_images.entry(food_kind.image.clone()).or_insert_with(|| asset_server.load(&food_kind.image));
+        game_assets.food_sounds.entry(food_kind.sound.clone()).or_insert_with(|| asset_server.load(&food_kind.sound));
+    }
+}
@@ -0,0 +1,333 @@
+//! src/game_config_mod.rs
+//! Configurable board size and game rules.
+//! The defaults are overwritten by the asset game_config.config.ron
+//! and then by the url query parameters like ?width=30&height=15&step=0.1&length=5&birds=1&walls=wrap
+//! The difficulty curve is ?min_step=0.08&speed_up=0.03
+//! The swipe gesture is ?swipe_distance=30&swipe_time=0.5 and the gamepad stick ?dead_zone=0.5
+//! The AI assist is ?ai_assist=true and the two player mode ?players=2
+//! The level is ?level=maze, the level decides the board size, see level_mod
+
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::on_game_board;
+use crate::snake_game_mod::{DEFAULT_STEP_DURATION, GameRules, Walls};
+use crate::web_sys_mod as wsm;
+
+#[derive(Resource, Asset, TypePath, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// seconds between two steps of the snake at the start
+    pub step_duration: f64,
+    /// the floor of the difficulty curve, the snake never gets faster than this
+    pub min_step_duration: f64,
+    /// every point shortens the step by this fraction
+    pub speed_up: f64,
+    /// minimum distance of a swipe in pixels, shorter is a tap
+    pub swipe_min_distance: f32,
+    /// a swipe must be faster than this seconds
+    pub swipe_max_duration: f32,
+    /// the gamepad stick inside this radius is ignored, from 0.0 to 1.0
+    pub stick_dead_zone: f32,
+    /// the computer turns the snake when the player does not
+    pub ai_assist: bool,
+    /// the name of the level, empty is the open board
+    pub level: String,
+    pub rules: GameRules,
+    /// the board size of the config and the url, a level changes the size of rules and the open board gets this size back
+    #[serde(skip)]
+    pub open_board_size: (i32, i32),
+}
+
+/// the width and the height of a playable board
+const BOARD_SIZES: std::ops::RangeInclusive<i32> = 5..=200;
+
+/// the file name without .config.ron
+const DEFAULT_CONFIG_NAME: &str = "game_config";
+
+#[derive(Resource)]
+pub struct GameConfigHandle(pub Handle<GameConfig>);
+
+/// the config of the player, while a replay or an online game plays with another config
+#[derive(Resource)]
+pub struct SavedGameConfig(pub GameConfig);
+
+#[derive(Default)]
+struct GameConfigLoader;
+
+#[derive(Debug, thiserror::Error)]
+enum GameConfigLoaderError {
+    #[error("Could not read the game config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the game config: {0}")]
+    Ron(#[from] bevy::asset::ron::error::SpannedError),
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        let rules = GameRules::default();
+        GameConfig {
+            step_duration: DEFAULT_STEP_DURATION,
+            min_step_duration: 0.08,
+            speed_up: 0.03,
+            swipe_min_distance: 30.0,
+            swipe_max_duration: 0.5,
+            stick_dead_zone: 0.5,
+            ai_assist: false,
+            level: String::new(),
+            open_board_size: (rules.board_width, rules.board_height),
+            rules,
+        }
+    }
+}
+
+impl GameConfig {
+    /// the url query parameters overwrite the values of the config
+    pub fn with_url_params(mut self) -> GameConfig {
+        if let Some(step_duration) = wsm::get_url_query_param("step").and_then(|value| value.parse().ok()) {
+            self.step_duration = step_duration;
+        }
+        if let Some(min_step_duration) = wsm::get_url_query_param("min_step").and_then(|value| value.parse().ok()) {
+            self.min_step_duration = min_step_duration;
+        }
+        if let Some(speed_up) = wsm::get_url_query_param("speed_up").and_then(|value| value.parse().ok()) {
+            self.speed_up = speed_up;
+        }
+        if let Some(swipe_min_distance) = wsm::get_url_query_param("swipe_distance").and_then(|value| value.parse().ok()) {
+            self.swipe_min_distance = swipe_min_distance;
+        }
+        if let Some(swipe_max_duration) = wsm::get_url_query_param("swipe_time").and_then(|value| value.parse().ok()) {
+            self.swipe_max_duration = swipe_max_duration;
+        }
+        if let Some(stick_dead_zone) = wsm::get_url_query_param("dead_zone").and_then(|value| value.parse().ok()) {
+            self.stick_dead_zone = stick_dead_zone;
+        }
+        if let Some(ai_assist) = wsm::get_url_query_param("ai_assist").and_then(|value| value.parse().ok()) {
+            self.ai_assist = ai_assist;
+        }
+        if let Some(level) = wsm::get_url_query_param("level") {
+            self.level = level;
+        }
+        if let Some(board_width) = wsm::get_url_query_param("width").and_then(|value| value.parse().ok()) {
+            self.rules.board_width = board_width;
+        }
+        if let Some(board_height) = wsm::get_url_query_param("height").and_then(|value| value.parse().ok()) {
+            self.rules.board_height = board_height;
+        }
+        if let Some(initial_length) = wsm::get_url_query_param("length").and_then(|value| value.parse().ok()) {
+            self.rules.initial_length = initial_length;
+        }
+        if let Some(bird_count) = wsm::get_url_query_param("birds").and_then(|value| value.parse().ok()) {
+            self.rules.bird_count = bird_count;
+        }
+        if let Some(snake_count) = wsm::get_url_query_param("players").and_then(|value| value.parse().ok()) {
+            self.rules.snake_count = snake_count;
+        }
+        if let Some(walls) = wsm::get_url_query_param("walls").and_then(|value| parse_walls(&value)) {
+            self.rules.walls = walls;
+        }
+        self.sanitized()
+    }
+
+    /// a board smaller than 5x5 or a step faster than 20ms is not playable.
+    /// A number like inf or NaN from the url gets the default, Duration and the fixed timestep panic on them.
+    pub fn sanitized(mut self) -> GameConfig {
+        let default = GameConfig::default();
+        self.rules.board_width = self.rules.board_width.clamp(*BOARD_SIZES.start(), *BOARD_SIZES.end());
+        self.rules.board_height = self.rules.board_height.clamp(*BOARD_SIZES.start(), *BOARD_SIZES.end());
+        self.rules.snake_count = self.rules.snake_count.clamp(1, 2);
+        // SnakeGame::new scans the whole board for every bird, a million birds freeze the page
+        let (board_width, board_height) = (self.rules.board_width as usize, self.rules.board_height as usize);
+        self.rules.bird_count = self.rules.bird_count.clamp(1, board_width * board_height);
+        self.rules.initial_length = self.rules.initial_length.clamp(2, board_width.max(board_height));
+        self.step_duration = sanitized_step_duration(self.step_duration);
+        self.min_step_duration = finite_or(self.min_step_duration, default.min_step_duration).clamp(0.02, self.step_duration);
+        self.speed_up = finite_or(self.speed_up, default.speed_up).clamp(0.0, 0.5);
+        self.swipe_min_distance = finite_or(self.swipe_min_distance, default.swipe_min_distance).clamp(5.0, 500.0);
+        self.swipe_max_duration = finite_or(self.swipe_max_duration, default.swipe_max_duration).clamp(0.05, 5.0);
+        self.stick_dead_zone = finite_or(self.stick_dead_zone, default.stick_dead_zone).clamp(0.05, 0.95);
+        self.open_board_size = (self.rules.board_width, self.rules.board_height);
+        self
+    }
+
+    /// The difficulty curve: the snake gets faster with every point, down to the floor min_step_duration.
+    pub fn step_duration_for_points(&self, points: i32) -> f64 {
+        (self.step_duration * (1.0 - self.speed_up).powi(points)).max(self.min_step_duration)
+    }
+}
+
+/// the fixed timestep panics on a step that is not positive and finite, also a step from the server goes through here
+pub fn sanitized_step_duration(step_duration: f64) -> f64 {
+    finite_or(step_duration, DEFAULT_STEP_DURATION).clamp(0.02, 2.0)
+}
+
+/// the board from the server must fit on the screen like the board of the config
+pub fn is_board_size_valid(rules: &GameRules) -> bool {
+    BOARD_SIZES.contains(&rules.board_width) && BOARD_SIZES.contains(&rules.board_height)
+}
+
+fn finite_or<T: Into<f64> + Copy>(value: T, default: T) -> T {
+    if value.into().is_finite() { value } else { default }
+}
+
+fn parse_walls(value: &str) -> Option<Walls> {
+    match value {
+        "solid" => Some(Walls::Solid),
+        "wrap" => Some(Walls::Wrap),
+        _ => None,
+    }
+}
+
+impl AssetLoader for GameConfigLoader {
+    type Asset = GameConfig;
+    type Settings = ();
+    type Error = GameConfigLoaderError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(bevy::asset::ron::de::from_bytes::<GameConfig>(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["config.ron"]
+    }
+}
+
+pub fn add_game_config_to_app(app: &mut App) {
+    app.init_asset::<GameConfig>();
+    app.init_asset_loader::<GameConfigLoader>();
+    app.add_systems(Startup, load_game_config);
+    // the board cannot change in the middle of the game
+    app.add_systems(Update, apply_game_config_asset.run_if(not(on_game_board)));
+}
+
+/// ?config=food_party loads food_party.config.ron instead of game_config.config.ron
+fn load_game_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let name = wsm::get_url_query_param("config")
+        .filter(|name| !name.is_empty() && name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_'))
+        .unwrap_or_else(|| DEFAULT_CONFIG_NAME.to_string());
+    commands.insert_resource(GameConfigHandle(asset_server.load(format!("{name}.config.ron"))));
+}
+
+/// the config of the player comes back after the replay or the online game
+pub fn restore_game_config(mut commands: Commands, saved_game_config: Option<Res<SavedGameConfig>>, mut game_config: ResMut<GameConfig>) {
+    if let Some(saved_game_config) = saved_game_config {
+        *game_config = saved_game_config.0.clone();
+        commands.remove_resource::<SavedGameConfig>();
+    }
+}
+
+/// when the asset is loaded or modified, it becomes the new GameConfig resource
+pub fn apply_game_config_asset(
+    mut asset_events: EventReader<AssetEvent<GameConfig>>,
+    game_config_assets: Res<Assets<GameConfig>>,
+    game_config_handle: Option<Res<GameConfigHandle>>,
+    mut game_config: ResMut<GameConfig>,
+) {
+    let Some(game_config_handle) = game_config_handle else {
+        return;
+    };
+    if !asset_events
+        .read()
+        .any(|event| event.is_loaded_with_dependencies(&game_config_handle.0) || event.is_modified(&game_config_handle.0))
+    {
+        return;
+    }
+    if let Some(game_config_asset) = game_config_assets.get(&game_config_handle.0) {
+        debug!("game config {game_config_asset:?}");
+        *game_config = game_config_asset.clone().with_url_params();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_game_mod::FoodKind;
+
+    fn parse_config(text: &str) -> GameConfig {
+        bevy::asset::ron::de::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn default_config_has_the_classic_bird() {
+        let game_config = parse_config(include_str!("../web_server_folder/snake_bevy_wasm/assets/game_config.config.ron"));
+        assert_eq!(game_config.rules.food_kinds, vec![FoodKind::default()]);
+    }
+
+    #[test]
+    fn food_party_config_has_more_kinds() {
+        let game_config = parse_config(include_str!("../web_server_folder/snake_bevy_wasm/assets/food_party.config.ron"));
+        assert!(game_config.rules.food_kinds.len() > 1);
+        assert_eq!(game_config.rules.food_kinds[0].name, "bird");
+        // every kind has its own image and its own sound
+        let food_kinds = &game_config.rules.food_kinds;
+        for (index, food_kind) in food_kinds.iter().enumerate() {
+            let is_same_look = |other: &FoodKind| other.image == food_kind.image;
+            let is_same_sound = |other: &FoodKind| other.sound == food_kind.sound;
+            assert!(!food_kinds[index + 1..].iter().any(is_same_look), "{} looks like another kind", food_kind.name);
+            assert!(!food_kinds[index + 1..].iter().any(is_same_sound), "{} sounds like another kind", food_kind.name);
+        }
+    }
+
+    #[test]
+    fn sanitized_replaces_infinite_and_huge_numbers() {
+        let default = GameConfig::default();
+        let game_config = GameConfig {
+            step_duration: f64::INFINITY,
+            min_step_duration: f64::NAN,
+            speed_up: f64::NEG_INFINITY,
+            swipe_min_distance: f32::NAN,
+            swipe_max_duration: f32::INFINITY,
+            stick_dead_zone: f32::NAN,
+            ..GameConfig::default()
+        }
+        .sanitized();
+        assert_eq!(game_config.step_duration, default.step_duration);
+        assert_eq!(game_config.min_step_duration, default.min_step_duration);
+        assert_eq!(game_config.speed_up, default.speed_up);
+        assert_eq!(game_config.swipe_min_distance, default.swipe_min_distance);
+        assert_eq!(game_config.swipe_max_duration, default.swipe_max_duration);
+        assert_eq!(game_config.stick_dead_zone, default.stick_dead_zone);
+
+        let game_config = GameConfig {
+            rules: GameRules {
+                board_width: 200,
+                board_height: 30,
+                bird_count: 1_000_000,
+                initial_length: 1_000_000,
+                ..GameRules::default()
+            },
+            step_duration: 1e300,
+            min_step_duration: 1e300,
+            speed_up: 1e300,
+            swipe_min_distance: 1e30,
+            swipe_max_duration: 1e30,
+            stick_dead_zone: 1e30,
+            ..GameConfig::default()
+        }
+        .sanitized();
+        assert_eq!(game_config.step_duration, 2.0);
+        assert_eq!(game_config.min_step_duration, 2.0);
+        assert_eq!(game_config.speed_up, 0.5);
+        assert_eq!(game_config.swipe_min_distance, 500.0);
+        assert_eq!(game_config.swipe_max_duration, 5.0);
+        assert_eq!(game_config.stick_dead_zone, 0.95);
+        assert_eq!(game_config.rules.bird_count, 200 * 30);
+        assert_eq!(game_config.rules.initial_length, 200);
+        // the slowest step still makes a Duration
+        let _ = std::time::Duration::from_secs_f64(game_config.step_duration_for_points(0));
+    }
+
+    #[test]
+    fn step_and_board_from_the_server_are_checked() {
+        assert_eq!(sanitized_step_duration(0.0), 0.02);
+        assert_eq!(sanitized_step_duration(-1.0), 0.02);
+        assert_eq!(sanitized_step_duration(f64::NAN), DEFAULT_STEP_DURATION);
+        assert_eq!(sanitized_step_duration(0.1), 0.1);
+        assert!(is_board_size_valid(&GameRules::default()));
+        assert!(!is_board_size_valid(&GameRules { board_width: 0, ..GameRules::default() }));
+        assert!(!is_board_size_valid(&GameRules { board_height: 100_000, ..GameRules::default() }));
+    }
+}
@@ -0,0 +1,44 @@
+//! src/game_rng_mod.rs
+//! Seedable random number generator for the game.
+//! Every random decision goes through the GameRng resource,
+//! so the same seed and the same inputs replay exactly the same game.
+
+use bevy::prelude::*;
+
+use crate::snake_game_mod::{RandomSource, SplitMix64};
+use crate::web_sys_mod as wsm;
+
+/// the SplitMix64 from snake_game_mod, the server has the same
+#[derive(Resource, Clone, Debug)]
+pub struct GameRng {
+    seed: u64,
+    rng: SplitMix64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        GameRng { seed, rng: SplitMix64::new(seed) }
+    }
+
+    /// The seed is from the url query parameter ?seed=123.
+    /// With ?seed=daily everybody plays the same game today.
+    /// Without the parameter the seed is from the clock.
+    pub fn from_url_or_clock() -> GameRng {
+        let seed = match wsm::get_url_query_param("seed") {
+            Some(seed) if seed == "daily" => wsm::get_utc_date_number(),
+            Some(seed) => seed.parse::<u64>().unwrap_or_else(|_| wsm::now_milliseconds() as u64),
+            None => wsm::now_milliseconds() as u64,
+        };
+        GameRng::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RandomSource for GameRng {
+    fn next_f64(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+}
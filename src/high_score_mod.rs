@@ -0,0 +1,257 @@
+//! src/high_score_mod.rs
+//! The top results are saved in the browser window.localStorage.
+//! A result is recorded when the game ends and the list is shown in the main menu and on the dead screen.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_assets_mod::GameAssets;
+use crate::game_config_mod::GameConfig;
+use crate::game_rng_mod::GameRng;
+use crate::snake_game_mod::{GameRules, Walls};
+use crate::state_in_game_mod::{GameOver, SnakeGameRes};
+use crate::theme_mod::Theme;
+use crate::web_sys_mod as wsm;
+use crate::{AppState, GameBoardCanvas};
+
+/// only the best results are saved
+pub const HIGH_SCORE_COUNT: usize = 10;
+/// the menus have space only for the first rows
+const HIGH_SCORE_VISIBLE_COUNT: usize = 5;
+const STORAGE_KEY: &str = "snake_bevy_wasm_high_scores";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HighScore {
+    pub points: i32,
+    pub moves: i32,
+    pub length: usize,
+    /// UTC date like 2025-08-11
+    pub date: String,
+    /// board size and walls like 20x20 solid
+    pub mode: String,
+    /// with this seed the game can be played again
+    pub seed: u64,
+}
+
+/// sorted from the best to the worst result
+#[derive(Resource, Default, Debug)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+    /// the rank of the last recorded game, None if it was not good enough for the list
+    pub last_rank: Option<usize>,
+}
+
+pub fn add_high_score_to_app(app: &mut App) {
+    app.insert_resource(HighScores::load());
+    // the result must be recorded before the dead screen shows the list
+    app.add_systems(OnEnter(AppState::Dead), record_high_score.before(crate::state_dead_mod::on_enter_dead));
+    // a completed level of the campaign is also a result
+    app.add_systems(OnEnter(AppState::LevelComplete), record_high_score);
+}
+
+impl HighScores {
+    /// a missing or broken storage is an empty list
+    pub fn load() -> HighScores {
+        let entries = wsm::local_storage_get(STORAGE_KEY)
+            .and_then(|text| bevy::asset::ron::from_str::<Vec<HighScore>>(&text).ok())
+            .unwrap_or_default();
+        HighScores { entries, last_rank: None }
+    }
+
+    pub fn save(&self) {
+        match bevy::asset::ron::to_string(&self.entries) {
+            Ok(text) => wsm::local_storage_set(STORAGE_KEY, &text),
+            Err(error) => error!("Could not serialize the high scores: {error}"),
+        }
+    }
+
+    /// Insert the result into the sorted list and return its rank.
+    /// More points is better, with the same points less moves is better.
+    pub fn record(&mut self, high_score: HighScore) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| high_score.points > entry.points || (high_score.points == entry.points && high_score.moves < entry.moves))
+            .unwrap_or(self.entries.len());
+        if rank >= HIGH_SCORE_COUNT {
+            self.last_rank = None;
+            return None;
+        }
+        self.entries.insert(rank, high_score);
+        self.entries.truncate(HIGH_SCORE_COUNT);
+        self.last_rank = Some(rank);
+        Some(rank)
+    }
+
+    /// the last game is better than all the games before
+    pub fn is_personal_best(&self) -> bool {
+        self.last_rank == Some(0)
+    }
+}
+
+/// board size and walls like 20x20 solid, the level, the two player games and the games with AI assist are marked
+pub fn mode_name(rules: &GameRules, level_name: &str, ai_assist: bool) -> String {
+    let walls = match rules.walls {
+        Walls::Solid => "solid",
+        Walls::Wrap => "wrap",
+    };
+    let players = if rules.snake_count > 1 { " 2p" } else { "" };
+    let level = if level_name.is_empty() { String::new() } else { format!(" {level_name}") };
+    let ai = if ai_assist { " ai" } else { "" };
+    format!("{}x{} {walls}{level}{players}{ai}", rules.board_width, rules.board_height)
+}
+
+fn record_high_score(game_over: Option<Res<GameOver>>, game: Option<Res<SnakeGameRes>>, game_rng: Option<Res<GameRng>>, game_config: Res<GameConfig>, mut high_scores: ResMut<HighScores>) {
+    let (Some(game_over), Some(game)) = (game_over, game) else {
+        return;
+    };
+    // the dead screen must not highlight the row of the game before
+    if game_over.is_replay || game_over.is_online {
+        high_scores.last_rank = None;
+        return;
+    }
+    let rank = high_scores.record(HighScore {
+        points: game_over.points,
+        moves: game_over.moves,
+        length: game_over.length,
+        date: wsm::get_utc_date_string(),
+        mode: mode_name(&game.0.rules, &game.0.level.name, game_config.ai_assist),
+        seed: game_rng.map_or(0, |game_rng| game_rng.seed()),
+    });
+    if rank.is_some() {
+        high_scores.save();
+    }
+}
+
+/// The table of high scores as text rows, the row of the last game is highlighted.
+/// highlight_last_game is false in the main menu. The highlight has the title color of the theme.
+pub fn spawn_high_score_table(parent: &mut ChildSpawnerCommands, game_board_canvas: &GameBoardCanvas, game_assets: &GameAssets, theme: &Theme, high_scores: &HighScores, highlight_last_game: bool) {
+    let font_size = game_board_canvas.sprite_height * 0.6;
+    if high_scores.entries.is_empty() {
+        parent.spawn((
+            Text::new("No high scores yet"),
+            game_assets.text_font(font_size),
+            TextLayout::new_with_justify(JustifyText::Center),
+            TextColor(theme.text_color()),
+        ));
+        return;
+    }
+    if highlight_last_game && high_scores.is_personal_best() {
+        parent.spawn((
+            Text::new("New personal best!"),
+            game_assets.text_font(game_board_canvas.sprite_height),
+            TextLayout::new_with_justify(JustifyText::Center),
+            TextColor(theme.title_color()),
+        ));
+    }
+    for (rank, entry) in high_scores.entries.iter().take(HIGH_SCORE_VISIBLE_COUNT).enumerate() {
+        let color = if highlight_last_game && high_scores.last_rank == Some(rank) {
+            theme.title_color()
+        } else {
+            theme.text_color()
+        };
+        parent.spawn((
+            Text::new(format!(
+                "{}. {} points {} moves length {} {} {} seed {}",
+                rank + 1,
+                entry.points,
+                entry.moves,
+                entry.length,
+                entry.date,
+                entry.mode,
+                entry.seed
+            )),
+            game_assets.text_font(font_size),
+            TextLayout::new_with_justify(JustifyText::Center),
+            TextColor(color),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::snake_game_mod::{DeathCause, Level, SnakeGame, SplitMix64};
+    use crate::state_in_game_mod::GameOverCause;
+
+    fn high_score(points: i32, moves: i32) -> HighScore {
+        HighScore {
+            points,
+            moves,
+            length: 3,
+            date: "2025-08-11".to_string(),
+            mode: "20x20 solid".to_string(),
+            seed: 1,
+        }
+    }
+
+    fn points_and_moves(high_scores: &HighScores) -> Vec<(i32, i32)> {
+        high_scores.entries.iter().map(|entry| (entry.points, entry.moves)).collect()
+    }
+
+    #[test]
+    fn record_sorts_by_points_then_by_less_moves() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.record(high_score(5, 50)), Some(0));
+        assert_eq!(high_scores.record(high_score(9, 90)), Some(0));
+        assert_eq!(high_scores.record(high_score(5, 40)), Some(1));
+        assert_eq!(high_scores.record(high_score(5, 60)), Some(3));
+        assert_eq!(points_and_moves(&high_scores), vec![(9, 90), (5, 40), (5, 50), (5, 60)]);
+        assert_eq!(high_scores.last_rank, Some(3));
+        assert!(!high_scores.is_personal_best());
+    }
+
+    #[test]
+    fn record_keeps_only_the_best_results() {
+        let mut high_scores = HighScores::default();
+        for points in 1..=HIGH_SCORE_COUNT as i32 + 2 {
+            high_scores.record(high_score(points, 10));
+        }
+        assert_eq!(high_scores.entries.len(), HIGH_SCORE_COUNT);
+        assert_eq!(high_scores.entries[0].points, HIGH_SCORE_COUNT as i32 + 2);
+        assert_eq!(high_scores.entries[HIGH_SCORE_COUNT - 1].points, 3);
+        assert!(high_scores.is_personal_best());
+    }
+
+    #[test]
+    fn record_clears_the_rank_of_a_result_that_is_not_good_enough() {
+        let mut high_scores = HighScores::default();
+        for _ in 0..HIGH_SCORE_COUNT {
+            high_scores.record(high_score(10, 10));
+        }
+        assert_eq!(high_scores.record(high_score(10, 11)), None);
+        assert_eq!(high_scores.last_rank, None);
+        assert_eq!(high_scores.entries.len(), HIGH_SCORE_COUNT);
+    }
+
+    #[test]
+    fn replay_and_online_games_are_not_recorded() {
+        for (is_replay, is_online) in [(true, false), (false, true)] {
+            let mut world = World::new();
+            let mut high_scores = HighScores::default();
+            high_scores.record(high_score(5, 50));
+            world.insert_resource(high_scores);
+            world.insert_resource(GameConfig::default());
+            let rules = GameConfig::default().rules;
+            world.insert_resource(SnakeGameRes(SnakeGame::new(&rules, &Level::default(), &mut SplitMix64::new(1))));
+            world.insert_resource(GameOver {
+                cause: GameOverCause::Died(DeathCause::Wall),
+                points: 100,
+                moves: 10,
+                length: 3,
+                snake_points: vec![100],
+                winner: None,
+                survived: Duration::from_secs(10),
+                is_replay,
+                is_online,
+            });
+            world.run_system_once(record_high_score).unwrap();
+            let high_scores = world.resource::<HighScores>();
+            assert_eq!(points_and_moves(high_scores), vec![(5, 50)]);
+            assert_eq!(high_scores.last_rank, None);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! src/input_intent_mod.rs
+//! One intent layer for all the input sources.
+//! Keyboard, on-screen buttons, touch swipes, gamepads and any future input source only send Intent events.
+//! The turns are validated in one place: the DirectionQueue of the snake.
+
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+
+use crate::{AppState, GameBoardCanvas, game_config_mod::GameConfig, snake_game_mod::Direction};
+
+/// the player index is also the snake index in SnakeGame.snakes
+pub const PLAYER_ONE: usize = 0;
+pub const PLAYER_TWO: usize = 1;
+
+#[derive(Event, Clone, PartialEq, Debug)]
+pub enum Intent {
+    /// player index and the new direction, in a single player game every player steers the only snake
+    Turn(usize, Direction),
+    /// start a new game from MainMenu or Dead, resume from Paused
+    Start,
+    /// pause or resume the game
+    Pause,
+    /// quit to the main menu
+    Quit,
+    /// watch the last game again
+    Replay,
+    /// a setting or a screen of the main menu
+    Menu(MenuAction),
+}
+
+/// the keys of the main menu, they do not collide with the keys of the players
+#[derive(Clone, PartialEq, Debug)]
+pub enum MenuAction {
+    ToggleWalls,
+    ToggleAiAssist,
+    TogglePlayers,
+    SelectLevel,
+    PlayOnline,
+    NextTheme,
+}
+
+/// start time of the touch and if the swipe is already sent
+struct TouchStart {
+    elapsed_secs: f32,
+    is_swipe_sent: bool,
+}
+
+pub fn add_input_intent_to_app(app: &mut App) {
+    app.add_event::<Intent>();
+    app.add_systems(Update, (send_keyboard_intents, send_touch_intents, send_gamepad_intents));
+}
+
+/// the same keys in all states, the states read only the intents they need
+fn send_keyboard_intents(keys: Res<ButtonInput<KeyCode>>, mut intents: EventWriter<Intent>) {
+    for key in keys.get_just_pressed() {
+        if let Some(intent) = key_to_intent(key) {
+            intents.write(intent);
+        }
+    }
+}
+
+pub fn key_to_intent(key: &KeyCode) -> Option<Intent> {
+    match key {
+        // WASD for player one, arrows for player two
+        KeyCode::KeyW => Some(Intent::Turn(PLAYER_ONE, Direction::Up)),
+        KeyCode::KeyD => Some(Intent::Turn(PLAYER_ONE, Direction::Right)),
+        KeyCode::KeyS => Some(Intent::Turn(PLAYER_ONE, Direction::Down)),
+        KeyCode::KeyA => Some(Intent::Turn(PLAYER_ONE, Direction::Left)),
+        KeyCode::ArrowUp => Some(Intent::Turn(PLAYER_TWO, Direction::Up)),
+        KeyCode::ArrowRight => Some(Intent::Turn(PLAYER_TWO, Direction::Right)),
+        KeyCode::ArrowDown => Some(Intent::Turn(PLAYER_TWO, Direction::Down)),
+        KeyCode::ArrowLeft => Some(Intent::Turn(PLAYER_TWO, Direction::Left)),
+        KeyCode::KeyN => Some(Intent::Start),
+        KeyCode::KeyP | KeyCode::Escape => Some(Intent::Pause),
+        KeyCode::KeyX => Some(Intent::Quit),
+        KeyCode::KeyR => Some(Intent::Replay),
+        KeyCode::KeyB => Some(Intent::Menu(MenuAction::ToggleWalls)),
+        KeyCode::KeyI => Some(Intent::Menu(MenuAction::ToggleAiAssist)),
+        KeyCode::Digit2 => Some(Intent::Menu(MenuAction::TogglePlayers)),
+        KeyCode::KeyL => Some(Intent::Menu(MenuAction::SelectLevel)),
+        KeyCode::KeyO => Some(Intent::Menu(MenuAction::PlayOnline)),
+        KeyCode::KeyT => Some(Intent::Menu(MenuAction::NextTheme)),
+        _ => None,
+    }
+}
+
+/// A swipe on the game board is a turn. It is sent as soon as it is long enough, without waiting for the release.
+/// A tap anywhere is a start.
+fn send_touch_intents(
+    touches: Res<Touches>,
+    time: Res<Time>,
+    game_config: Res<GameConfig>,
+    game_board_canvas: Res<GameBoardCanvas>,
+    mut touch_starts: Local<HashMap<u64, TouchStart>>,
+    mut intents: EventWriter<Intent>,
+) {
+    for touch in touches.iter_just_pressed() {
+        touch_starts.insert(
+            touch.id(),
+            TouchStart {
+                elapsed_secs: time.elapsed_secs(),
+                is_swipe_sent: false,
+            },
+        );
+    }
+    for touch in touches.iter() {
+        let Some(touch_start) = touch_starts.get_mut(&touch.id()) else {
+            continue;
+        };
+        let start_position = touch.start_position();
+        let is_on_board = start_position.x < game_board_canvas.board_canvas_width as f32 && start_position.y < game_board_canvas.board_canvas_height as f32;
+        let is_in_time = time.elapsed_secs() - touch_start.elapsed_secs <= game_config.swipe_max_duration;
+        if touch_start.is_swipe_sent || !is_on_board || !is_in_time {
+            continue;
+        }
+        if let Some(direction) = swipe_direction(touch.distance(), game_config.swipe_min_distance) {
+            intents.write(Intent::Turn(PLAYER_ONE, direction));
+            touch_start.is_swipe_sent = true;
+        }
+    }
+    for touch in touches.iter_just_released() {
+        let is_swipe_sent = touch_starts.remove(&touch.id()).is_none_or(|touch_start| touch_start.is_swipe_sent);
+        if !is_swipe_sent && touch.distance().length() < game_config.swipe_min_distance {
+            intents.write(Intent::Start);
+        }
+    }
+    for touch in touches.iter_just_canceled() {
+        touch_starts.remove(&touch.id());
+    }
+}
+
+/// The longer axis of the swipe wins. The window coordinates have y down.
+pub fn swipe_direction(distance: Vec2, min_distance: f32) -> Option<Direction> {
+    if distance.length() < min_distance {
+        None
+    } else if distance.x.abs() > distance.y.abs() {
+        Some(if distance.x > 0.0 { Direction::Right } else { Direction::Left })
+    } else {
+        Some(if distance.y > 0.0 { Direction::Down } else { Direction::Up })
+    }
+}
+
+/// The D-pad and the left stick steer, South or Start begins a game, West replays, Select quits to the main menu.
+/// In the game and in the replay the Start button pauses and resumes.
+/// The stick sends a turn only when it leaves the dead zone or changes direction.
+fn send_gamepad_intents(
+    gamepads: Query<(Entity, &Gamepad)>,
+    game_config: Res<GameConfig>,
+    app_state: Res<State<AppState>>,
+    mut stick_directions: Local<HashMap<Entity, Direction>>,
+    mut intents: EventWriter<Intent>,
+) {
+    for (entity, gamepad) in gamepads {
+        for button in gamepad.get_just_pressed() {
+            let intent = match button {
+                GamepadButton::DPadUp => Some(Intent::Turn(PLAYER_ONE, Direction::Up)),
+                GamepadButton::DPadRight => Some(Intent::Turn(PLAYER_ONE, Direction::Right)),
+                GamepadButton::DPadDown => Some(Intent::Turn(PLAYER_ONE, Direction::Down)),
+                GamepadButton::DPadLeft => Some(Intent::Turn(PLAYER_ONE, Direction::Left)),
+                GamepadButton::Start if matches!(app_state.get(), AppState::InGame | AppState::Replay) => Some(Intent::Pause),
+                GamepadButton::South | GamepadButton::Start => Some(Intent::Start),
+                GamepadButton::Select => Some(Intent::Quit),
+                GamepadButton::West => Some(Intent::Replay),
+                _ => None,
+            };
+            if let Some(intent) = intent {
+                intents.write(intent);
+            }
+        }
+
+        // the gamepad stick has y up
+        match stick_direction(gamepad.left_stick(), game_config.stick_dead_zone) {
+            Some(direction) => {
+                if stick_directions.get(&entity) != Some(&direction) {
+                    intents.write(Intent::Turn(PLAYER_ONE, direction.clone()));
+                    stick_directions.insert(entity, direction);
+                }
+            }
+            None => {
+                stick_directions.remove(&entity);
+            }
+        }
+    }
+}
+
+/// The longer axis of the stick wins. The stick has y up.
+pub fn stick_direction(stick: Vec2, dead_zone: f32) -> Option<Direction> {
+    swipe_direction(Vec2::new(stick.x, -stick.y), dead_zone)
+}
@@ -0,0 +1,178 @@
+//! src/level_mod.rs
+//! The levels are ASCII grids in assets/levels/*.level.ron, the name of the level is the file name.
+//! The grid becomes snake_game_mod::Level with the walls, the starts of the snakes and the bird zones.
+//! The selected level is GameConfig.level, also from the url ?level=maze. Empty is the open board.
+
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::game_config_mod::GameConfig;
+use crate::snake_game_mod::{Level, LevelGoal};
+use crate::{AppState, on_game_board};
+
+/// the web server cannot list a folder, so the levels are listed here in the order of the level select screen
+pub const LEVEL_NAMES: [&str; 3] = ["box", "cross", "maze"];
+
+#[derive(Asset, TypePath, Debug)]
+pub struct LevelAsset(pub Level);
+
+/// the content of the file
+#[derive(Deserialize)]
+struct LevelFile {
+    grid: Vec<String>,
+    /// the campaign goes to the next level when the goal is reached
+    #[serde(default)]
+    goal: Option<LevelGoal>,
+}
+
+/// all the levels, loaded at startup
+#[derive(Resource, Default)]
+pub struct Levels {
+    handles: Vec<Handle<LevelAsset>>,
+    /// the loaded levels in the order of LEVEL_NAMES, a level that failed to load is missing
+    pub loaded: Vec<Level>,
+    /// every level is loaded or failed
+    pub is_complete: bool,
+}
+
+#[derive(Default)]
+struct LevelLoader;
+
+#[derive(Debug, thiserror::Error)]
+enum LevelLoaderError {
+    #[error("Could not read the level: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the level: {0}")]
+    Ron(#[from] bevy::asset::ron::error::SpannedError),
+    #[error("Wrong grid: {0}")]
+    Grid(String),
+}
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let level_file = bevy::asset::ron::de::from_bytes::<LevelFile>(&bytes)?;
+        let file_name = load_context.path().file_name().map_or(String::new(), |file_name| file_name.to_string_lossy().to_string());
+        let name = file_name.trim_end_matches(".level.ron");
+        let mut level = Level::from_grid(name, &level_file.grid).map_err(LevelLoaderError::Grid)?;
+        level.goal = level_file.goal;
+        Ok(LevelAsset(level))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+impl Levels {
+    pub fn find(&self, name: &str) -> Option<&Level> {
+        self.loaded.iter().find(|level| level.name == name)
+    }
+
+    /// the selected level of the config, the open board if it is not selected or not loaded
+    pub fn level_for(&self, game_config: &GameConfig) -> Level {
+        self.find(&game_config.level).cloned().unwrap_or_default()
+    }
+}
+
+pub fn add_level_to_app(app: &mut App) {
+    app.init_asset::<LevelAsset>();
+    app.init_asset_loader::<LevelLoader>();
+    app.init_resource::<Levels>();
+    app.add_systems(Startup, load_levels);
+    app.add_systems(Update, collect_loaded_levels);
+    // the board cannot change in the middle of the game, the online game has the board from the server
+    app.add_systems(Update, apply_level_board_size.run_if(not(on_game_board).and(not(in_state(AppState::Lobby)))));
+}
+
+fn load_levels(asset_server: Res<AssetServer>, mut levels: ResMut<Levels>) {
+    levels.handles = LEVEL_NAMES.iter().map(|name| asset_server.load(format!("levels/{name}.level.ron"))).collect();
+}
+
+/// a modified level file is loaded again
+fn collect_loaded_levels(mut asset_events: EventReader<AssetEvent<LevelAsset>>, level_assets: Res<Assets<LevelAsset>>, asset_server: Res<AssetServer>, mut levels: ResMut<Levels>) {
+    let is_changed = asset_events.read().count() > 0;
+    let is_complete = levels.handles.iter().all(|handle| asset_server.is_loaded(handle) || asset_server.load_state(handle).is_failed());
+    if !is_changed && is_complete == levels.is_complete {
+        return;
+    }
+    levels.loaded = levels.handles.iter().filter_map(|handle| level_assets.get(handle)).map(|level_asset| level_asset.0.clone()).collect();
+    if is_complete && !levels.is_complete {
+        info!("levels loaded: {}/{}", levels.loaded.len(), LEVEL_NAMES.len());
+    }
+    levels.is_complete = is_complete;
+}
+
+/// the level decides the size of the board, None is the open board with the size from the config
+pub fn select_level(game_config: &mut GameConfig, level: Option<&Level>) {
+    game_config.level = level.map_or(String::new(), |level| level.name.clone());
+    (game_config.rules.board_width, game_config.rules.board_height) = level.map_or(game_config.open_board_size, |level| (level.board_width, level.board_height));
+}
+
+/// The level from the config or from the url gets its board size when it is loaded.
+/// The change detection of GameConfig is triggered only if something changes.
+fn apply_level_board_size(levels: Res<Levels>, mut game_config: ResMut<GameConfig>) {
+    if game_config.level.is_empty() || !levels.is_complete {
+        return;
+    }
+    let Some(level) = levels.find(&game_config.level) else {
+        warn!("unknown level {}", game_config.level);
+        game_config.level.clear();
+        return;
+    };
+    if game_config.rules.board_width != level.board_width || game_config.rules.board_height != level.board_height {
+        select_level(&mut game_config, Some(level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_game_mod::{LEVEL_START_COUNT, Position};
+
+    /// the same as the LevelLoader, without the asset server
+    fn parse_level(name: &str, text: &str) -> Level {
+        let level_file: LevelFile = bevy::asset::ron::de::from_str(text).unwrap();
+        let mut level = Level::from_grid(name, &level_file.grid).unwrap();
+        level.goal = level_file.goal;
+        level
+    }
+
+    #[test]
+    fn shipped_levels_parse() {
+        let levels = [
+            parse_level("box", include_str!("../web_server_folder/snake_bevy_wasm/assets/levels/box.level.ron")),
+            parse_level("cross", include_str!("../web_server_folder/snake_bevy_wasm/assets/levels/cross.level.ron")),
+            parse_level("maze", include_str!("../web_server_folder/snake_bevy_wasm/assets/levels/maze.level.ron")),
+        ];
+        for (level, name) in levels.iter().zip(LEVEL_NAMES) {
+            assert_eq!(level.name, name);
+            assert_eq!(level.starts.len(), LEVEL_START_COUNT);
+            // the campaign needs a goal in every level
+            assert!(level.goal.is_some(), "level {name} has no goal");
+        }
+        assert_eq!((levels[0].board_width, levels[0].board_height), (20, 20));
+        assert!(levels[0].is_wall(&Position { x: 0, y: 0 }));
+    }
+
+    #[test]
+    fn open_board_gets_the_size_of_the_config_back() {
+        let mut game_config = GameConfig::default();
+        game_config.rules.board_width = 30;
+        game_config.rules.board_height = 15;
+        let mut game_config = game_config.sanitized();
+        let level = parse_level("cross", include_str!("../web_server_folder/snake_bevy_wasm/assets/levels/cross.level.ron"));
+        select_level(&mut game_config, Some(&level));
+        assert_eq!(game_config.level, "cross");
+        assert_eq!((game_config.rules.board_width, game_config.rules.board_height), (level.board_width, level.board_height));
+        select_level(&mut game_config, None);
+        assert_eq!(game_config.level, "");
+        assert_eq!((game_config.rules.board_width, game_config.rules.board_height), (30, 15));
+    }
+}
@@ -71,20 +71,63 @@ use wasm_bindgen::prelude::*;
 
 mod web_sys_mod;
 use web_sys_mod as wsm;
+mod autopilot_mod;
+mod campaign_mod;
+mod game_assets_mod;
+mod game_config_mod;
+use game_config_mod::GameConfig;
+mod game_rng_mod;
+mod high_score_mod;
+mod input_intent_mod;
+mod level_mod;
+mod net_protocol_mod;
+mod online_mod;
+mod replay_mod;
+mod snake_controller_mod;
+mod snake_game_mod;
 mod state_dead_mod;
 mod state_in_game_mod;
+mod state_level_select_mod;
+mod state_loading_mod;
 mod state_main_menu_mod;
+mod state_paused_mod;
+mod theme_mod;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const BOARD_WIDTH: i32 = 20;
-const BOARD_HEIGHT: i32 = 20;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, States)]
 #[states(scoped_entities)]
 enum AppState {
+    /// the images and sounds are loaded before the main menu
+    Loading,
     MainMenu,
     InGame,
     Dead,
+    /// a recorded game plays again with the same in-game systems
+    Replay,
+    /// connecting to the online server and waiting for the other player
+    Lobby,
+    /// the online game renders the snapshots from the server with the same in-game systems
+    Online,
+    /// choose the level from the main menu
+    LevelSelect,
+    /// the goal of the level is reached, the transition to the next level of the campaign
+    LevelComplete,
+}
+
+/// InGame, Replay or Online is running or paused, the game entities stay alive while paused
+#[derive(SubStates, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[source(AppState = AppState::InGame | AppState::Replay | AppState::Online)]
+#[states(scoped_entities)]
+enum InGameState {
+    #[default]
+    Running,
+    Paused,
+}
+
+/// run condition for the systems of the game board, they run also in the replay and in the online game
+fn on_game_board(app_state: Res<State<AppState>>) -> bool {
+    matches!(app_state.get(), AppState::InGame | AppState::Replay | AppState::Online)
 }
 
 #[derive(PartialEq)]
@@ -110,8 +153,28 @@ pub struct GameBoardCanvas {
 struct CustomMaterial {
     #[uniform(0)]
     screen_size: Vec2,
+    #[uniform(1)]
+    background: BackgroundPalette,
 }
 
+// the ShaderType derive generates a size check per field that is never called,
+// an allow on the struct does not reach it, so the struct has its own module
+#[allow(dead_code)]
+mod background_palette {
+    use bevy::{prelude::*, render::render_resource::ShaderType};
+
+    /// the cosine palette a + b * cos(2pi * (c * t + d)) of the background shader, from the theme
+    #[derive(ShaderType, Debug, Clone)]
+    pub struct BackgroundPalette {
+        pub a: Vec3,
+        pub b: Vec3,
+        pub c: Vec3,
+        pub d: Vec3,
+        pub speed: f32,
+    }
+}
+use background_palette::BackgroundPalette;
+
 /// The Material trait is very configurable, but comes with sensible defaults for all methods.
 /// You only need to implement functions for features that need non-default behavior. See the Material api docs for details!
 impl Material2d for CustomMaterial {
@@ -134,7 +197,8 @@ pub fn main() {
 
     // bevy initiation
     let mut app = bevy::app::App::new();
-    let game_board_canvas = get_game_board_canvas();
+    let game_config = GameConfig::default().with_url_params();
+    let game_board_canvas = get_game_board_canvas(&game_config);
 
     app.add_plugins((
         DefaultPlugins
@@ -166,21 +230,37 @@ pub fn main() {
     ));
 
     info!("started snake_bevy_wasm {}", VERSION);
-    // initial state is MainMenu
-    app.insert_state(AppState::MainMenu);
+    // initial state is Loading, then MainMenu
+    app.insert_state(AppState::Loading);
+    app.add_sub_state::<InGameState>();
 
     app.insert_resource(game_board_canvas);
+    app.insert_resource(game_config);
+    game_config_mod::add_game_config_to_app(&mut app);
+    game_assets_mod::add_game_assets_to_app(&mut app);
+    theme_mod::add_theme_to_app(&mut app);
+    state_loading_mod::add_loading_to_app(&mut app);
+    level_mod::add_level_to_app(&mut app);
+    input_intent_mod::add_input_intent_to_app(&mut app);
+    high_score_mod::add_high_score_to_app(&mut app);
+    replay_mod::add_replay_to_app(&mut app);
+    autopilot_mod::add_autopilot_to_app(&mut app);
+    online_mod::add_online_to_app(&mut app);
     state_main_menu_mod::add_main_menu_to_app(&mut app);
+    state_level_select_mod::add_level_select_to_app(&mut app);
+    campaign_mod::add_campaign_to_app(&mut app);
 
     state_in_game_mod::add_in_game_to_app(&mut app);
 
+    state_paused_mod::add_paused_to_app(&mut app);
+
     state_dead_mod::add_dead_to_app(&mut app);
 
     app.run();
 }
 
 // setup fit to window size on startup
-fn get_game_board_canvas() -> GameBoardCanvas {
+fn get_game_board_canvas(game_config: &GameConfig) -> GameBoardCanvas {
     // check viewport and define sizes
     let client_width = wsm::get_client_width();
     let client_height = wsm::get_client_height();
@@ -194,27 +274,47 @@ fn get_game_board_canvas() -> GameBoardCanvas {
     } else {
         (client_width, Orientation::Portrait)
     };
-    // return
-    GameBoardCanvas {
+    let mut game_board_canvas = GameBoardCanvas {
         client_width,
         client_height,
         scale_factor,
         orientation,
         board_canvas_width: game_square_width,
         board_canvas_height: game_square_width,
-        sprite_width: game_square_width as f32 / BOARD_WIDTH as f32,
-        sprite_height: game_square_width as f32 / BOARD_HEIGHT as f32,
+        sprite_width: 0.,
+        sprite_height: 0.,
+    };
+    game_board_canvas.fit_board(game_square_width, game_config);
+    // return
+    game_board_canvas
+}
+
+impl GameBoardCanvas {
+    /// The cells are square. The longer side of the board fits into the game square.
+    fn fit_board(&mut self, game_square_width: i32, game_config: &GameConfig) {
+        let board_width = game_config.rules.board_width;
+        let board_height = game_config.rules.board_height;
+        let sprite_size = game_square_width as f32 / board_width.max(board_height) as f32;
+        self.board_canvas_width = (sprite_size * board_width as f32) as i32;
+        self.board_canvas_height = (sprite_size * board_height as f32) as i32;
+        self.sprite_width = sprite_size;
+        self.sprite_height = sprite_size;
     }
 }
 
 /// Bevy 0.16 is not setting the correct width and height for the canvas element.
 /// I don't know why. The css style is correct, but the attributes of the html element are wrong.
 /// I must check this and correct this size frequently.
-pub fn handle_browser_resize(mut game_board_canvas: ResMut<GameBoardCanvas>, mut window: bevy::ecs::system::Single<&mut bevy::window::Window, bevy::ecs::query::With<bevy::window::PrimaryWindow>>) {
+/// The sprite size depends also on the board size in GameConfig.
+pub fn handle_browser_resize(
+    mut game_board_canvas: ResMut<GameBoardCanvas>,
+    game_config: Res<GameConfig>,
+    mut window: bevy::ecs::system::Single<&mut bevy::window::Window, bevy::ecs::query::With<bevy::window::PrimaryWindow>>,
+) {
     let client_width = wsm::get_client_width();
     let client_height = wsm::get_client_height();
 
-    if (window.resolution.width() as i32) != client_width || (window.resolution.height() as i32) != client_height {
+    if (window.resolution.width() as i32) != client_width || (window.resolution.height() as i32) != client_height || game_config.is_changed() {
         // debug!("handle_browser_resize {client_width} {client_height}");
         game_board_canvas.client_width = client_width;
         game_board_canvas.client_height = client_height;
@@ -227,10 +327,7 @@ pub fn handle_browser_resize(mut game_board_canvas: ResMut<GameBoardCanvas>, mut
             (client_width, Orientation::Portrait)
         };
         game_board_canvas.orientation = orientation;
-        game_board_canvas.board_canvas_width = game_square_width;
-        game_board_canvas.board_canvas_height = game_square_width;
-        game_board_canvas.sprite_width = game_square_width as f32 / BOARD_WIDTH as f32;
-        game_board_canvas.sprite_height = game_square_width as f32 / BOARD_HEIGHT as f32;
+        game_board_canvas.fit_board(game_square_width, &game_config);
 
         window.resolution.set(client_width as f32, client_height as f32);
     }
@@ -0,0 +1,43 @@
+//! src/net_protocol_mod.rs
+//! The messages between the wasm client and the online server snake_server, pure Rust like snake_game_mod.
+//! The server includes this file with #[path], so both sides always have the same protocol.
+//! Every message is one WebSocket text frame with the message serialized as RON.
+//! The server is authoritative: the clients send only their turns and render the snapshots from the server.
+
+use serde::{Deserialize, Serialize};
+
+use crate::snake_game_mod::{Direction, GameEvent, SnakeGame};
+
+/// a client with another version gets an error on join
+pub const PROTOCOL_VERSION: u32 = 5;
+/// the reference server listens on localhost, so the online game can be tested offline
+pub const DEFAULT_SERVER_URL: &str = "ws://localhost:9001";
+pub const DEFAULT_ROOM: &str = "lobby";
+/// an online game is always for two players, only the server uses it
+#[allow(dead_code)]
+pub const ROOM_PLAYER_COUNT: usize = 2;
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum ClientMessage {
+    /// join the room with this name, the room is created by the first player
+    Join { room: String, version: u32 },
+    /// the new direction of the own snake, the server queues it like the local game does
+    Turn(Direction),
+    /// leave the room, the same as closing the connection
+    Leave,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ServerMessage {
+    /// the player index is also the snake index in SnakeGame.snakes
+    Joined { room: String, player_index: usize },
+    /// the players in the room, the game starts when the room is full
+    Lobby { players: usize, needed: usize },
+    /// the first state of the game, the clients step with the same duration as the server
+    Started { step_duration: f64, game: SnakeGame },
+    /// The authoritative state after the step with this tick and the events of this step.
+    /// The tick is SnakeGame.moves, a client ignores an older snapshot.
+    Snapshot { tick: i32, game: SnakeGame, events: Vec<GameEvent> },
+    /// the message was not understood, the version is wrong or the room is already playing
+    Error(String),
+}
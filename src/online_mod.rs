@@ -0,0 +1,282 @@
+//! src/online_mod.rs
+//! The online game against another player over the authoritative server snake_server.
+//! The key O in the main menu opens the lobby, it connects to ?server=ws://localhost:9001 and joins ?room=lobby
+//! In AppState::Online the in-game systems render the snapshots from the server instead of stepping SnakeGame.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::game_assets_mod::GameAssets;
+use crate::game_config_mod::{GameConfig, SavedGameConfig, is_board_size_valid, restore_game_config, sanitized_step_duration};
+use crate::input_intent_mod::Intent;
+use crate::net_protocol_mod::{ClientMessage, DEFAULT_ROOM, DEFAULT_SERVER_URL, PROTOCOL_VERSION, ServerMessage};
+use crate::snake_game_mod::{GameEvent, SnakeGame};
+use crate::theme_mod::Theme;
+use crate::web_sys_mod as wsm;
+use crate::{AppState, GameBoardCanvas};
+
+/// The WebSocket is not Send, so it is a NonSend resource.
+/// None when not connected.
+#[derive(Default)]
+pub struct OnlineConnection(Option<wsm::WebSocketConnection>);
+
+/// exists only in AppState::Lobby
+#[derive(Resource)]
+struct Lobby {
+    server_url: String,
+    room: String,
+    is_join_sent: bool,
+    /// the snake index of this browser, from the Joined message
+    player_index: Option<usize>,
+    status: String,
+}
+
+/// exists from the Started message until the end of the online game
+#[derive(Resource)]
+pub struct OnlineSession {
+    /// the snake index of this browser
+    pub player_index: usize,
+    /// seconds between two steps on the server, the fixed timestep of the client is the same
+    pub step_duration: f64,
+    /// the first state of the game for on_enter_in_game
+    pub initial_game: SnakeGame,
+    /// the snapshots received since the last fixed step
+    snapshots: VecDeque<(SnakeGame, Vec<GameEvent>)>,
+    /// the tick of the last snapshot, an older snapshot is ignored
+    last_tick: i32,
+    pub is_connection_lost: bool,
+}
+
+#[derive(Component)]
+struct LobbyText;
+
+pub fn add_online_to_app(app: &mut App) {
+    app.insert_non_send_resource(OnlineConnection::default());
+    app.add_systems(OnEnter(AppState::Lobby), on_enter_lobby);
+    app.add_systems(OnExit(AppState::Lobby), on_exit_lobby);
+    app.add_systems(OnExit(AppState::Online), (on_exit_online, restore_game_config));
+    app.add_systems(
+        Update,
+        (
+            handle_lobby_messages.run_if(in_state(AppState::Lobby)),
+            handle_lobby_input.run_if(in_state(AppState::Lobby)),
+            render_lobby_text.run_if(in_state(AppState::Lobby)),
+            crate::handle_browser_resize.run_if(in_state(AppState::Lobby)),
+            receive_snapshots.run_if(in_state(AppState::Online)),
+            send_online_turns.run_if(in_state(AppState::Online)),
+        ),
+    );
+}
+
+impl OnlineSession {
+    fn new(player_index: usize, step_duration: f64, initial_game: SnakeGame) -> OnlineSession {
+        OnlineSession {
+            player_index,
+            step_duration,
+            last_tick: initial_game.moves,
+            initial_game,
+            snapshots: VecDeque::new(),
+            is_connection_lost: false,
+        }
+    }
+
+    /// all the snapshots since the last call, in the order from the server
+    pub fn take_snapshots(&mut self) -> Vec<(SnakeGame, Vec<GameEvent>)> {
+        self.snapshots.drain(..).collect()
+    }
+}
+
+fn send_message(connection: &wsm::WebSocketConnection, message: &ClientMessage) {
+    match bevy::asset::ron::to_string(message) {
+        Ok(text) => {
+            if !connection.send(&text) {
+                warn!("Could not send {text}");
+            }
+        }
+        Err(error) => error!("Could not serialize the message: {error}"),
+    }
+}
+
+/// a message that cannot be parsed is only logged
+fn receive_messages(connection: &wsm::WebSocketConnection) -> Vec<ServerMessage> {
+    connection
+        .receive()
+        .into_iter()
+        .filter_map(|text| match bevy::asset::ron::from_str::<ServerMessage>(&text) {
+            Ok(message) => Some(message),
+            Err(error) => {
+                error!("Could not parse the message from the server: {error}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// connect and show the status, the Join is sent when the connection is open
+fn on_enter_lobby(mut commands: Commands, game_board_canvas: Res<GameBoardCanvas>, game_assets: Res<GameAssets>, theme: Res<Theme>, mut connection: NonSendMut<OnlineConnection>) {
+    let server_url = wsm::get_url_query_param("server").unwrap_or_else(|| DEFAULT_SERVER_URL.to_string());
+    let room = wsm::get_url_query_param("room").unwrap_or_else(|| DEFAULT_ROOM.to_string());
+    connection.0 = wsm::WebSocketConnection::connect(&server_url);
+    let status = match &connection.0 {
+        Some(_) => format!("Connecting to {server_url}"),
+        None => format!("The server url {server_url} is not valid"),
+    };
+    commands.insert_resource(Lobby {
+        server_url,
+        room,
+        is_join_sent: false,
+        player_index: None,
+        status,
+    });
+
+    commands.spawn((StateScoped(AppState::Lobby), Camera2d));
+    commands
+        .spawn((
+            StateScoped(AppState::Lobby),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+        ))
+        .with_children(|lobby| {
+            lobby.spawn((
+                Text::new("Online game"),
+                game_assets.text_font(game_board_canvas.sprite_height * 2.),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.title_color()),
+            ));
+            lobby.spawn((
+                Text::new(""),
+                game_assets.text_font(game_board_canvas.sprite_height),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.text_color()),
+                LobbyText,
+            ));
+            lobby.spawn((
+                Text::new("Press X to cancel"),
+                game_assets.text_font(game_board_canvas.sprite_height),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.hint_color()),
+            ));
+        });
+}
+
+/// the connection stays open only if the game started
+fn on_exit_lobby(mut commands: Commands, mut connection: NonSendMut<OnlineConnection>, online_session: Option<Res<OnlineSession>>) {
+    commands.remove_resource::<Lobby>();
+    if online_session.is_none() {
+        connection.0 = None;
+    }
+}
+
+/// the server forgets the player when the connection is closed
+fn on_exit_online(mut commands: Commands, mut connection: NonSendMut<OnlineConnection>) {
+    commands.remove_resource::<OnlineSession>();
+    connection.0 = None;
+}
+
+/// join the room, wait for the other player and start when the server starts the game
+fn handle_lobby_messages(mut commands: Commands, connection: NonSend<OnlineConnection>, mut lobby: ResMut<Lobby>, mut game_config: ResMut<GameConfig>, mut next_state: ResMut<NextState<AppState>>) {
+    let Some(connection) = &connection.0 else {
+        return;
+    };
+    if !lobby.is_join_sent && connection.is_open() {
+        send_message(
+            connection,
+            &ClientMessage::Join {
+                room: lobby.room.clone(),
+                version: PROTOCOL_VERSION,
+            },
+        );
+        lobby.is_join_sent = true;
+        lobby.status = format!("Joining the room {}", lobby.room);
+    }
+    for message in receive_messages(connection) {
+        match message {
+            ServerMessage::Joined { room, player_index } => {
+                lobby.player_index = Some(player_index);
+                lobby.status = format!("Room {room}: you are player {}", player_index + 1);
+            }
+            ServerMessage::Lobby { players, needed } => {
+                let player = lobby.player_index.map_or(0, |player_index| player_index + 1);
+                lobby.status = format!("Room {}: you are player {player}\nWaiting for players {players}/{needed}", lobby.room);
+            }
+            ServerMessage::Started { step_duration, game } => {
+                // a wrong message from the server must not panic the client
+                if !is_board_size_valid(&game.rules) {
+                    lobby.status = format!("Error: the server sent the board {}x{}", game.rules.board_width, game.rules.board_height);
+                    continue;
+                }
+                let step_duration = sanitized_step_duration(step_duration);
+                // the board has the size from the server already in on_enter_in_game
+                // the config of the player is restored when the online game is over
+                commands.insert_resource(SavedGameConfig(game_config.clone()));
+                game_config.rules = game.rules.clone();
+                commands.insert_resource(OnlineSession::new(lobby.player_index.unwrap_or(0), step_duration, game));
+                next_state.set(AppState::Online);
+                return;
+            }
+            ServerMessage::Error(error) => lobby.status = format!("Error: {error}"),
+            ServerMessage::Snapshot { .. } => {}
+        }
+    }
+    if connection.is_closed() {
+        lobby.status = format!("No connection to {}", lobby.server_url);
+    }
+}
+
+fn handle_lobby_input(mut intents: EventReader<Intent>, mut next_state: ResMut<NextState<AppState>>) {
+    if intents.read().any(|intent| *intent == Intent::Quit) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+fn render_lobby_text(lobby: Res<Lobby>, mut lobby_text_query: Query<&mut Text, With<LobbyText>>) {
+    if !lobby.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = lobby_text_query.single_mut() {
+        *text = Text::new(lobby.status.clone());
+    }
+}
+
+/// the snapshots wait in the OnlineSession for the next fixed step, see apply_online_snapshot
+fn receive_snapshots(connection: NonSend<OnlineConnection>, mut online_session: ResMut<OnlineSession>) {
+    let Some(connection) = &connection.0 else {
+        online_session.is_connection_lost = true;
+        return;
+    };
+    for message in receive_messages(connection) {
+        match message {
+            ServerMessage::Snapshot { tick, game, events } if tick >= online_session.last_tick => {
+                online_session.last_tick = tick;
+                online_session.snapshots.push_back((game, events));
+            }
+            ServerMessage::Error(error) => error!("online error: {error}"),
+            _ => {}
+        }
+    }
+    if connection.is_closed() {
+        online_session.is_connection_lost = true;
+    }
+}
+
+/// every input source turns the own snake, there is no pause in the online game
+fn send_online_turns(mut intents: EventReader<Intent>, connection: NonSend<OnlineConnection>, mut next_state: ResMut<NextState<AppState>>) {
+    for intent in intents.read() {
+        match intent {
+            Intent::Turn(_, direction) => {
+                if let Some(connection) = &connection.0 {
+                    send_message(connection, &ClientMessage::Turn(direction.clone()));
+                }
+            }
+            Intent::Quit => next_state.set(AppState::MainMenu),
+            Intent::Start | Intent::Pause | Intent::Replay | Intent::Menu(_) => {}
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! src/replay_mod.rs
+//! Every game is recorded as the seed, the config and the list of turns with the tick when they happened.
+//! With the same seed and the same turns SnakeGame plays exactly the same game again.
+//! The recording is shared as url fragment like #replay=r4_123_20_20_3_3_s_1_maze_0.2_0.08_0.03_5f2a1c3e_4L9U
+//! The level is only the name, the replay plays on the level with this name from level_mod.
+//! The food kinds are only a hash, the replay plays with the kinds of the config and refuses other kinds.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_config_mod::{GameConfig, SavedGameConfig, restore_game_config};
+use crate::input_intent_mod::Intent;
+use crate::level_mod::Levels;
+use crate::snake_game_mod::{Direction, FoodKind, GameRules, Walls};
+use crate::web_sys_mod as wsm;
+use crate::{AppState, InGameState};
+
+const COMPACT_VERSION: &str = "r4";
+/// r3 is the same without the hash of the food kinds, it is from before the food kinds
+const R3_COMPACT_VERSION: &str = "r3";
+/// r2 is the same as r3 without the level
+const R2_COMPACT_VERSION: &str = "r2";
+/// the open board in the compact string, the names of the levels have no underscore
+const NO_LEVEL: &str = "-";
+const URL_FRAGMENT_PREFIX: &str = "replay=";
+const MIN_PLAYBACK_SPEED: f64 = 0.25;
+const MAX_PLAYBACK_SPEED: f64 = 8.0;
+
+/// the direction that was applied to one snake in the step with this tick
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct Turn {
+    /// the number of moves before this step
+    pub tick: i32,
+    pub snake_index: usize,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Recording {
+    pub seed: u64,
+    pub rules: GameRules,
+    /// the name of the level, empty is the open board
+    pub level: String,
+    pub step_duration: f64,
+    pub min_step_duration: f64,
+    pub speed_up: f64,
+    /// the link has only the hash of the food kinds, the rules of a recording from a link have no kinds
+    pub food_kinds_hash: u32,
+    pub turns: Vec<Turn>,
+}
+
+/// the last game, played or replayed, move_snake_head adds the turns while playing
+#[derive(Resource)]
+pub struct GameRecording(pub Recording);
+
+/// exists only in AppState::Replay, the turns come from here instead of the input
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    recording: Recording,
+    next_turn: usize,
+    /// 1.0 is the recorded speed
+    pub speed: f64,
+}
+
+pub fn add_replay_to_app(app: &mut App) {
+    app.add_systems(Update, start_replay_from_url.run_if(in_state(AppState::MainMenu)));
+    app.add_systems(OnExit(AppState::Replay), (on_exit_replay, restore_game_config));
+    app.add_systems(OnEnter(AppState::Dead), share_replay_in_url);
+    app.add_systems(Update, handle_replay_input.run_if(in_state(AppState::Replay)));
+}
+
+impl Recording {
+    pub fn new(seed: u64, game_config: &GameConfig) -> Recording {
+        Recording {
+            seed,
+            rules: game_config.rules.clone(),
+            level: game_config.level.clone(),
+            step_duration: game_config.step_duration,
+            min_step_duration: game_config.min_step_duration,
+            speed_up: game_config.speed_up,
+            food_kinds_hash: food_kinds_hash(&game_config.rules.food_kinds),
+            turns: vec![],
+        }
+    }
+
+    /// Short text for the url: the fields are separated by underscore,
+    /// the turns are the tick followed by the first letter of the direction, lowercase for the second snake.
+    pub fn to_compact_string(&self) -> String {
+        let walls = match self.rules.walls {
+            Walls::Solid => "s",
+            Walls::Wrap => "w",
+        };
+        let turns: String = self
+            .turns
+            .iter()
+            .map(|turn| {
+                let direction = match turn.direction {
+                    Direction::Up => 'U',
+                    Direction::Down => 'D',
+                    Direction::Left => 'L',
+                    Direction::Right => 'R',
+                };
+                let direction = if turn.snake_index == 0 { direction } else { direction.to_ascii_lowercase() };
+                format!("{}{direction}", turn.tick)
+            })
+            .collect();
+        let level = if self.level.is_empty() { NO_LEVEL } else { self.level.as_str() };
+        format!(
+            "{COMPACT_VERSION}_{}_{}_{}_{}_{}_{walls}_{}_{level}_{}_{}_{}_{:x}_{turns}",
+            self.seed,
+            self.rules.board_width,
+            self.rules.board_height,
+            self.rules.initial_length,
+            self.rules.bird_count,
+            self.rules.snake_count,
+            self.step_duration,
+            self.min_step_duration,
+            self.speed_up,
+            self.food_kinds_hash
+        )
+    }
+
+    /// None if the text is not a valid recording
+    pub fn from_compact_string(text: &str) -> Option<Recording> {
+        let mut fields: Vec<&str> = text.split('_').collect();
+        // the old links without the level are on the open board
+        if fields.first() == Some(&R2_COMPACT_VERSION) && fields.len() == 12 {
+            fields[0] = R3_COMPACT_VERSION;
+            fields.insert(8, NO_LEVEL);
+        }
+        // the old links without the food kinds are with the classic bird
+        let classic_hash = format!("{:x}", food_kinds_hash(&[]));
+        if fields.first() == Some(&R3_COMPACT_VERSION) && fields.len() == 13 {
+            fields[0] = COMPACT_VERSION;
+            fields.insert(12, &classic_hash);
+        }
+        let [
+            version,
+            seed,
+            board_width,
+            board_height,
+            initial_length,
+            bird_count,
+            walls,
+            snake_count,
+            level,
+            step_duration,
+            min_step_duration,
+            speed_up,
+            food_kinds_hash,
+            turns,
+        ] = fields.as_slice()
+        else {
+            return None;
+        };
+        if *version != COMPACT_VERSION {
+            return None;
+        }
+        let walls = match *walls {
+            "s" => Walls::Solid,
+            "w" => Walls::Wrap,
+            _ => return None,
+        };
+        Some(Recording {
+            seed: seed.parse().ok()?,
+            rules: GameRules {
+                board_width: board_width.parse().ok()?,
+                board_height: board_height.parse().ok()?,
+                initial_length: initial_length.parse().ok()?,
+                bird_count: bird_count.parse().ok()?,
+                walls,
+                snake_count: snake_count.parse().ok()?,
+                // the link is too short for the food table, the replay uses the kinds from the config with the same hash
+                food_kinds: vec![],
+            },
+            level: if *level == NO_LEVEL { String::new() } else { level.to_string() },
+            step_duration: parse_in_range(step_duration, 0.02..=2.0)?,
+            min_step_duration: parse_in_range(min_step_duration, 0.02..=2.0)?,
+            speed_up: parse_in_range(speed_up, 0.0..=0.5)?,
+            food_kinds_hash: u32::from_str_radix(food_kinds_hash, 16).ok()?,
+            turns: parse_turns(turns)?,
+        })
+    }
+
+    /// a recording from a link plays only with the same food kinds, other kinds are another game
+    pub fn has_food_kinds(&self, food_kinds: &[FoodKind]) -> bool {
+        self.food_kinds_hash == food_kinds_hash(food_kinds)
+    }
+
+    /// the recorded game is played with the recorded config, a link from the url can have any values
+    pub fn apply_to_game_config(&self, game_config: &mut GameConfig) {
+        let food_kinds = std::mem::take(&mut game_config.rules.food_kinds);
+        game_config.rules = self.rules.clone();
+        if game_config.rules.food_kinds.is_empty() {
+            game_config.rules.food_kinds = food_kinds;
+        }
+        game_config.level = self.level.clone();
+        game_config.step_duration = self.step_duration;
+        game_config.min_step_duration = self.min_step_duration;
+        game_config.speed_up = self.speed_up;
+        *game_config = game_config.clone().sanitized();
+    }
+}
+
+/// FNV-1a of the fields that change the game, the look and the sound of a kind do not matter.
+/// Without kinds the game has the classic bird.
+fn food_kinds_hash(food_kinds: &[FoodKind]) -> u32 {
+    let classic = [FoodKind::default()];
+    let food_kinds = if food_kinds.is_empty() { &classic[..] } else { food_kinds };
+    let text: String = food_kinds
+        .iter()
+        .map(|food_kind| format!("{}:{}:{}:{:?}:{:?};", food_kind.points, food_kind.growth, food_kind.weight, food_kind.lifetime, food_kind.power_up))
+        .collect();
+    text.bytes().fold(0x811c9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+/// the ranges of GameConfig::sanitized, inf, NaN and huge numbers are not a link from this game
+fn parse_in_range(text: &str, range: std::ops::RangeInclusive<f64>) -> Option<f64> {
+    text.parse().ok().filter(|value| range.contains(value))
+}
+
+/// like 4L9U12R12u, lowercase is the second snake
+fn parse_turns(text: &str) -> Option<Vec<Turn>> {
+    let mut turns = vec![];
+    let mut tick = String::new();
+    for character in text.chars() {
+        if character.is_ascii_digit() {
+            tick.push(character);
+            continue;
+        }
+        let direction = match character.to_ascii_uppercase() {
+            'U' => Direction::Up,
+            'D' => Direction::Down,
+            'L' => Direction::Left,
+            'R' => Direction::Right,
+            _ => return None,
+        };
+        let snake_index = if character.is_ascii_uppercase() { 0 } else { 1 };
+        turns.push(Turn {
+            tick: tick.parse().ok()?,
+            snake_index,
+            direction,
+        });
+        tick.clear();
+    }
+    tick.is_empty().then_some(turns)
+}
+
+impl ReplayPlayback {
+    pub fn new(recording: Recording) -> ReplayPlayback {
+        ReplayPlayback { recording, next_turn: 0, speed: 1.0 }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// the recorded turns by snake index for the step with this tick, the turns are sorted by tick
+    pub fn inputs_for_tick(&mut self, tick: i32, snake_count: usize) -> Vec<Option<Direction>> {
+        let mut inputs = vec![None; snake_count];
+        while let Some(turn) = self.recording.turns.get(self.next_turn).filter(|turn| turn.tick == tick) {
+            if let Some(input) = inputs.get_mut(turn.snake_index) {
+                *input = Some(turn.direction.clone());
+            }
+            self.next_turn += 1;
+        }
+        inputs
+    }
+}
+
+/// The config of the recording replaces the GameConfig before the state changes,
+/// so the board has the recorded size already in on_enter_in_game.
+/// The config of the player is saved and restored on exit.
+pub fn start_replay(commands: &mut Commands, game_config: &mut GameConfig, next_state: &mut NextState<AppState>, recording: Recording) {
+    commands.insert_resource(SavedGameConfig(game_config.clone()));
+    recording.apply_to_game_config(game_config);
+    commands.insert_resource(ReplayPlayback::new(recording));
+    next_state.set(AppState::Replay);
+}
+
+/// The page was opened with #replay=... in the url.
+/// It waits in the main menu until the levels are loaded, the replay can be on a level.
+fn start_replay_from_url(mut commands: Commands, mut game_config: ResMut<GameConfig>, mut next_state: ResMut<NextState<AppState>>, levels: Res<Levels>, mut is_started: Local<bool>) {
+    if *is_started || !levels.is_complete {
+        return;
+    }
+    *is_started = true;
+    let Some(recording) = wsm::get_url_fragment().and_then(|fragment| fragment.strip_prefix(URL_FRAGMENT_PREFIX).and_then(Recording::from_compact_string)) else {
+        return;
+    };
+    if !recording.has_food_kinds(&game_config.rules.food_kinds) {
+        warn!("the replay from url was recorded with other food kinds than in the config");
+        return;
+    }
+    info!("replay from url, seed {}", recording.seed);
+    start_replay(&mut commands, &mut game_config, &mut next_state, recording);
+}
+
+fn on_exit_replay(mut commands: Commands) {
+    commands.remove_resource::<ReplayPlayback>();
+}
+
+/// after every game the url in the address bar is the link to the replay
+fn share_replay_in_url(game_recording: Option<Res<GameRecording>>) {
+    if let Some(game_recording) = game_recording {
+        wsm::set_url_fragment(&format!("{URL_FRAGMENT_PREFIX}{}", game_recording.0.to_compact_string()));
+    }
+}
+
+/// Up and Right are faster, Down and Left are slower, the pause works as in the game.
+fn handle_replay_input(
+    mut intents: EventReader<Intent>,
+    mut playback: ResMut<ReplayPlayback>,
+    in_game_state: Res<State<InGameState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_in_game_state: ResMut<NextState<InGameState>>,
+) {
+    let is_paused = *in_game_state.get() == InGameState::Paused;
+    for intent in intents.read() {
+        match intent {
+            Intent::Turn(_, Direction::Up | Direction::Right) => playback.speed = (playback.speed * 2.0).min(MAX_PLAYBACK_SPEED),
+            Intent::Turn(_, Direction::Down | Direction::Left) => playback.speed = (playback.speed * 0.5).max(MIN_PLAYBACK_SPEED),
+            Intent::Quit => next_state.set(AppState::MainMenu),
+            Intent::Pause | Intent::Start if is_paused => next_in_game_state.set(InGameState::Running),
+            Intent::Pause => next_in_game_state.set(InGameState::Paused),
+            Intent::Start | Intent::Replay | Intent::Menu(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_rng_mod::GameRng;
+    use crate::snake_game_mod::{Level, RandomSource, SnakeGame};
+
+    fn new_recording() -> Recording {
+        let game_config = GameConfig {
+            level: "maze".to_string(),
+            rules: GameRules {
+                board_width: 30,
+                board_height: 15,
+                walls: Walls::Wrap,
+                snake_count: 2,
+                ..GameRules::default()
+            },
+            ..GameConfig::default()
+        };
+        let mut recording = Recording::new(123, &game_config);
+        recording.turns = vec![
+            Turn {
+                tick: 4,
+                snake_index: 0,
+                direction: Direction::Left,
+            },
+            Turn {
+                tick: 12,
+                snake_index: 1,
+                direction: Direction::Up,
+            },
+        ];
+        recording
+    }
+
+    #[test]
+    fn compact_string_round_trip() {
+        let recording = new_recording();
+        let text = recording.to_compact_string();
+        assert!(text.starts_with("r4_123_30_15_3_3_w_2_maze_0.2_0.08_0.03_"));
+        assert!(text.ends_with("_4L12u"));
+        let parsed = Recording::from_compact_string(&text).unwrap();
+        assert_eq!(parsed.to_compact_string(), text);
+        assert_eq!(parsed.turns, recording.turns);
+        assert_eq!(parsed.level, "maze");
+        assert!(parsed.has_food_kinds(&recording.rules.food_kinds));
+    }
+
+    #[test]
+    fn compact_string_round_trip_open_board_without_turns() {
+        let recording = Recording {
+            level: String::new(),
+            turns: vec![],
+            ..new_recording()
+        };
+        let parsed = Recording::from_compact_string(&recording.to_compact_string()).unwrap();
+        assert_eq!(parsed.level, "");
+        assert!(parsed.turns.is_empty());
+    }
+
+    #[test]
+    fn old_links_have_the_classic_bird() {
+        let parsed = Recording::from_compact_string("r2_123_20_20_3_3_s_1_0.2_0.08_0.03_4L").unwrap();
+        assert_eq!(parsed.level, "");
+        assert!(parsed.has_food_kinds(&[FoodKind::default()]));
+        let parsed = Recording::from_compact_string("r3_123_20_20_3_3_s_1_maze_0.2_0.08_0.03_4L").unwrap();
+        assert_eq!(parsed.level, "maze");
+        assert!(parsed.has_food_kinds(&[]));
+        let golden = FoodKind { points: 5, ..FoodKind::default() };
+        assert!(!parsed.has_food_kinds(&[FoodKind::default(), golden]));
+    }
+
+    #[test]
+    fn from_compact_string_rejects_wrong_links() {
+        assert!(Recording::from_compact_string("").is_none());
+        assert!(Recording::from_compact_string("r9_123_20_20_3_3_s_1_-_0.2_0.08_0.03_0_4L").is_none());
+        assert!(Recording::from_compact_string("r4_123_20_20_3_3_x_1_-_0.2_0.08_0.03_0_4L").is_none());
+        assert!(Recording::from_compact_string("r4_abc_20_20_3_3_s_1_-_0.2_0.08_0.03_0_4L").is_none());
+        assert!(Recording::from_compact_string("r4_123_20_20_3_3_s_1_-_0.2_0.08_0.03_zz_4L").is_none());
+        // the durations panic in Duration and in the fixed timestep
+        assert!(Recording::from_compact_string("r4_123_20_20_3_3_s_1_-_inf_0.08_0.03_0_4L").is_none());
+        assert!(Recording::from_compact_string("r4_123_20_20_3_3_s_1_-_1e300_0.08_0.03_0_4L").is_none());
+        assert!(Recording::from_compact_string("r4_123_20_20_3_3_s_1_-_0.2_NaN_0.03_0_4L").is_none());
+        assert!(Recording::from_compact_string("r4_123_20_20_3_3_s_1_-_0.2_0.08_-1_0_4L").is_none());
+    }
+
+    #[test]
+    fn parse_turns_errors() {
+        assert_eq!(parse_turns(""), Some(vec![]));
+        // a tick without a direction at the end
+        assert_eq!(parse_turns("4L9"), None);
+        // a direction without a tick
+        assert_eq!(parse_turns("L"), None);
+        assert_eq!(parse_turns("4L9X"), None);
+        assert_eq!(parse_turns("4-L"), None);
+        // a tick too big for i32
+        assert_eq!(parse_turns("99999999999L"), None);
+    }
+
+    /// plays a game with random turns, the replay from the link must end in the same state
+    #[test]
+    fn replay_reproduces_the_game() {
+        let game_config = GameConfig {
+            rules: GameRules {
+                walls: Walls::Wrap,
+                snake_count: 2,
+                ..GameRules::default()
+            },
+            ..GameConfig::default()
+        };
+        let mut recording = Recording::new(7, &game_config);
+        let mut rng = GameRng::new(recording.seed);
+        let mut game = SnakeGame::new(&game_config.rules, &Level::default(), &mut rng);
+        let mut input_rng = GameRng::new(99);
+        while !game.is_over() && game.moves < 500 {
+            let inputs: Vec<Option<Direction>> = (0..2)
+                .map(|_| match input_rng.below(8) {
+                    0 => Some(Direction::Up),
+                    1 => Some(Direction::Down),
+                    2 => Some(Direction::Left),
+                    3 => Some(Direction::Right),
+                    _ => None,
+                })
+                .collect();
+            for (snake_index, input) in inputs.iter().enumerate() {
+                if let Some(direction) = input {
+                    recording.turns.push(Turn {
+                        tick: game.moves,
+                        snake_index,
+                        direction: direction.clone(),
+                    });
+                }
+            }
+            game.step(&inputs, &mut rng);
+        }
+
+        let recording = Recording::from_compact_string(&recording.to_compact_string()).unwrap();
+        let mut replay_config = game_config.clone();
+        recording.apply_to_game_config(&mut replay_config);
+        let mut rng = GameRng::new(recording.seed);
+        let mut replayed = SnakeGame::new(&replay_config.rules, &Level::default(), &mut rng);
+        let mut playback = ReplayPlayback::new(recording);
+        while replayed.moves < game.moves {
+            let inputs = playback.inputs_for_tick(replayed.moves, 2);
+            replayed.step(&inputs, &mut rng);
+        }
+        assert!(game.moves > 10);
+        assert_eq!(format!("{replayed:?}"), format!("{game:?}"));
+    }
+}
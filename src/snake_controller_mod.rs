@@ -0,0 +1,388 @@
+//! src/snake_controller_mod.rs
+//! Computer players for SnakeGame, pure Rust like snake_game_mod.
+//! A controller only chooses the next direction, the game rules are still in SnakeGame::step().
+//! The greedy A* controller runs to the nearest bird, the Hamiltonian controller follows a cycle over the whole board.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::snake_game_mod::{Direction, Position, Snake, SnakeGame, Walls};
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+
+/// every computer player implements this trait
+pub trait SnakeController {
+    /// the direction of the snake with this index for the next step, None keeps the current direction
+    fn next_direction(&mut self, game: &SnakeGame, snake_index: usize) -> Option<Direction>;
+}
+
+/// A* search to the nearest bird, without looking further.
+/// Without a path it takes the neighbour cell with the most free space around.
+#[derive(Default)]
+pub struct GreedyAStarController;
+
+/// Follows a cycle that visits every cell of the board once, so the snake never bites itself.
+/// A cycle exists only if the width or the height is even, else it is greedy.
+pub struct HamiltonianController {
+    /// for every cell index the index of the next cell in the cycle
+    successors: Option<Vec<usize>>,
+    greedy: GreedyAStarController,
+}
+
+impl SnakeController for GreedyAStarController {
+    fn next_direction(&mut self, game: &SnakeGame, snake_index: usize) -> Option<Direction> {
+        let blocked = blocked_cells(game, snake_index);
+        let snake = &game.snakes[snake_index];
+        a_star_first_step(game, snake, &blocked).or_else(|| most_space_direction(game, snake, &blocked))
+    }
+}
+
+impl HamiltonianController {
+    pub fn new(board_width: i32, board_height: i32) -> HamiltonianController {
+        HamiltonianController {
+            successors: hamiltonian_cycle(board_width, board_height),
+            greedy: GreedyAStarController,
+        }
+    }
+}
+
+impl SnakeController for HamiltonianController {
+    fn next_direction(&mut self, game: &SnakeGame, snake_index: usize) -> Option<Direction> {
+        let snake = &game.snakes[snake_index];
+        let head = &snake.head;
+        let width = game.rules.board_width;
+        // the snake can be off the cycle at the start, then the greedy controller steers
+        // the cycle goes through the walls of a level, so on a level the greedy controller always steers
+        let next_direction = self.successors.as_ref().filter(|_| game.is_inside(head) && game.level.walls.is_empty()).and_then(|successors| {
+            let next = successors[cell_index(width, head)] as i32;
+            let next = Position { x: next % width, y: next / width };
+            DIRECTIONS.into_iter().find(|direction| head.moved(direction) == next)
+        });
+        let blocked = blocked_cells(game, snake_index);
+        match next_direction {
+            Some(direction) if direction != snake.direction.opposite() && !blocked[cell_index(width, &head.moved(&direction))] => Some(direction),
+            _ => self.greedy.next_direction(game, snake_index),
+        }
+    }
+}
+
+fn cell_index(board_width: i32, position: &Position) -> usize {
+    (position.y * board_width + position.x) as usize
+}
+
+/// The cells occupied by the snake body, the own tail is free because it moves away in the next step, but not while the snake grows.
+/// The own head is blocked, the first segment will be there. The other snakes are blocked completely, also their heads.
+/// The walls of the level are always blocked.
+fn blocked_cells(game: &SnakeGame, snake_index: usize) -> Vec<bool> {
+    let mut blocked = vec![false; (game.rules.board_width * game.rules.board_height) as usize];
+    let mut block = |position: &Position| {
+        if game.is_inside(position) {
+            blocked[cell_index(game.rules.board_width, position)] = true;
+        }
+    };
+    for (other_index, snake) in game.snakes.iter().enumerate() {
+        if other_index == snake_index {
+            let moving_segment_count = if snake.growth > 0 { 0 } else { 1 };
+            block(&snake.head);
+            snake
+                .segments
+                .iter()
+                .take(snake.segments.len().saturating_sub(moving_segment_count))
+                .for_each(|segment| block(&segment.position));
+        } else {
+            block(&snake.head);
+            snake.segments.iter().for_each(|segment| block(&segment.position));
+        }
+    }
+    game.level.walls.iter().for_each(&mut block);
+    blocked
+}
+
+/// the free neighbour cells, with Walls::Wrap also over the edge
+fn free_neighbours(game: &SnakeGame, blocked: &[bool], position: &Position) -> Vec<(Direction, Position)> {
+    DIRECTIONS
+        .into_iter()
+        .map(|direction| {
+            let mut neighbour = position.moved(&direction);
+            if game.rules.walls == Walls::Wrap {
+                neighbour = game.rules.wrapped(&neighbour);
+            }
+            (direction, neighbour)
+        })
+        .filter(|(_, neighbour)| game.is_inside(neighbour) && !blocked[cell_index(game.rules.board_width, neighbour)])
+        .collect()
+}
+
+/// manhattan distance, with Walls::Wrap also over the edge
+fn distance(game: &SnakeGame, from: &Position, to: &Position) -> i32 {
+    let dx = (from.x - to.x).abs();
+    let dy = (from.y - to.y).abs();
+    match game.rules.walls {
+        Walls::Solid => dx + dy,
+        Walls::Wrap => dx.min(game.rules.board_width - dx) + dy.min(game.rules.board_height - dy),
+    }
+}
+
+/// the first direction of the shortest path to any bird
+fn a_star_first_step(game: &SnakeGame, snake: &Snake, blocked: &[bool]) -> Option<Direction> {
+    let width = game.rules.board_width;
+    let heuristic = |position: &Position| game.food.iter().filter(|food| !food.is_waiting).map(|food| distance(game, position, &food.position)).min().unwrap_or(0);
+    // for every visited cell: the cost from the head and the first direction of the path
+    let mut visited: Vec<Option<(i32, Direction)>> = vec![None; blocked.len()];
+    let mut open = BinaryHeap::new();
+    for (direction, neighbour) in free_neighbours(game, blocked, &snake.head) {
+        if direction == snake.direction.opposite() {
+            continue;
+        }
+        visited[cell_index(width, &neighbour)] = Some((1, direction));
+        open.push(Reverse((1 + heuristic(&neighbour), 1, neighbour.x, neighbour.y)));
+    }
+    while let Some(Reverse((_, cost, x, y))) = open.pop() {
+        let position = Position { x, y };
+        let (best_cost, first_direction) = visited[cell_index(width, &position)].clone()?;
+        if cost > best_cost {
+            continue;
+        }
+        if game.food.iter().any(|food| !food.is_waiting && food.position == position) {
+            return Some(first_direction);
+        }
+        for (_, neighbour) in free_neighbours(game, blocked, &position) {
+            let index = cell_index(width, &neighbour);
+            if visited[index].as_ref().is_none_or(|(neighbour_cost, _)| cost + 1 < *neighbour_cost) {
+                visited[index] = Some((cost + 1, first_direction.clone()));
+                open.push(Reverse((cost + 1 + heuristic(&neighbour), cost + 1, neighbour.x, neighbour.y)));
+            }
+        }
+    }
+    None
+}
+
+/// the direction into the biggest free area, None if the snake is trapped
+fn most_space_direction(game: &SnakeGame, snake: &Snake, blocked: &[bool]) -> Option<Direction> {
+    free_neighbours(game, blocked, &snake.head)
+        .into_iter()
+        .filter(|(direction, _)| *direction != snake.direction.opposite())
+        .max_by_key(|(_, neighbour)| flood_fill_count(game, blocked, neighbour))
+        .map(|(direction, _)| direction)
+}
+
+fn flood_fill_count(game: &SnakeGame, blocked: &[bool], start: &Position) -> usize {
+    let width = game.rules.board_width;
+    let mut visited = vec![false; blocked.len()];
+    let mut queue = VecDeque::from([start.clone()]);
+    visited[cell_index(width, start)] = true;
+    let mut count = 0;
+    while let Some(position) = queue.pop_front() {
+        count += 1;
+        for (_, neighbour) in free_neighbours(game, blocked, &position) {
+            let index = cell_index(width, &neighbour);
+            if !visited[index] {
+                visited[index] = true;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    count
+}
+
+/// The cycle goes right on the first row, zig-zags over the other columns and comes back up on the first column.
+/// It needs an even number of rows, with an odd number of rows the board is transposed.
+fn hamiltonian_cycle(board_width: i32, board_height: i32) -> Option<Vec<usize>> {
+    let transposed = board_height % 2 != 0;
+    let (width, height) = if transposed { (board_height, board_width) } else { (board_width, board_height) };
+    if height % 2 != 0 || width < 2 {
+        return None;
+    }
+    let mut path = vec![];
+    for x in 0..width {
+        path.push((x, 0));
+    }
+    for y in 1..height {
+        if y % 2 == 1 {
+            for x in (1..width).rev() {
+                path.push((x, y));
+            }
+        } else {
+            for x in 1..width {
+                path.push((x, y));
+            }
+        }
+    }
+    for y in (1..height).rev() {
+        path.push((0, y));
+    }
+    let mut successors = vec![0; path.len()];
+    for (order, (x, y)) in path.iter().enumerate() {
+        let (next_x, next_y) = path[(order + 1) % path.len()];
+        let (x, y, next_x, next_y) = if transposed { (*y, *x, next_y, next_x) } else { (*x, *y, next_x, next_y) };
+        successors[(y * board_width + x) as usize] = (next_y * board_width + next_x) as usize;
+    }
+    Some(successors)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::snake_game_mod::{Food, GameRules, Level, Segment, SplitMix64};
+
+    /// one snake on an empty board, the first cell is the head, the last is the tail
+    fn game_with_snake(board_width: i32, board_height: i32, cells: &[(i32, i32)], direction: Direction) -> SnakeGame {
+        let rules = GameRules {
+            board_width,
+            board_height,
+            ..GameRules::default()
+        };
+        let mut game = SnakeGame::new(&rules, &Level::default(), &mut SplitMix64::new(1));
+        game.food.clear();
+        let snake = &mut game.snakes[0];
+        snake.head = Position { x: cells[0].0, y: cells[0].1 };
+        snake.direction = direction.clone();
+        snake.segments = cells[1..]
+            .iter()
+            .map(|(x, y)| Segment {
+                position: Position { x: *x, y: *y },
+                direction: direction.clone(),
+                last_direction: direction.clone(),
+            })
+            .collect::<VecDeque<_>>();
+        game
+    }
+
+    fn add_bird(game: &mut SnakeGame, x: i32, y: i32) {
+        game.food.push(Food {
+            position: Position { x, y },
+            color: 0,
+            kind: 0,
+            expires_at: None,
+            is_waiting: false,
+        });
+    }
+
+    /// the snake is alive after one step in this direction
+    fn is_safe(game: &SnakeGame, direction: &Direction) -> bool {
+        let mut game = game.clone();
+        game.step(&[Some(direction.clone())], &mut SplitMix64::new(1));
+        !game.is_dead
+    }
+
+    #[test]
+    fn greedy_steers_to_the_bird() {
+        let snake = [(5, 5), (5, 4), (5, 3)];
+        for ((x, y), expected) in [((2, 5), Direction::Left), ((8, 5), Direction::Right), ((5, 8), Direction::Down)] {
+            let mut game = game_with_snake(10, 10, &snake, Direction::Down);
+            add_bird(&mut game, x, y);
+            assert_eq!(GreedyAStarController.next_direction(&game, 0), Some(expected), "bird at {x},{y}");
+        }
+        // the bird behind the body is reached around it
+        let mut game = game_with_snake(10, 10, &snake, Direction::Down);
+        add_bird(&mut game, 5, 1);
+        let direction = GreedyAStarController.next_direction(&game, 0).unwrap();
+        assert!(matches!(direction, Direction::Left | Direction::Right));
+    }
+
+    #[test]
+    fn greedy_does_not_turn_into_the_wall_or_the_body() {
+        // on the right edge with the bird straight behind
+        let mut game = game_with_snake(10, 10, &[(9, 5), (8, 5), (7, 5)], Direction::Right);
+        add_bird(&mut game, 0, 5);
+        let direction = GreedyAStarController.next_direction(&game, 0).unwrap();
+        assert!(matches!(direction, Direction::Up | Direction::Down));
+        assert!(is_safe(&game, &direction));
+    }
+
+    #[test]
+    fn greedy_never_dies_when_a_safe_move_exists() {
+        let rules = GameRules {
+            board_width: 8,
+            board_height: 8,
+            ..GameRules::default()
+        };
+        let mut rng = SplitMix64::new(7);
+        let mut game = SnakeGame::new(&rules, &Level::default(), &mut rng);
+        for _ in 0..500 {
+            if game.is_over() {
+                break;
+            }
+            let has_safe_move = DIRECTIONS
+                .iter()
+                .filter(|direction| **direction != game.snakes[0].direction.opposite())
+                .any(|direction| is_safe(&game, direction));
+            let direction = GreedyAStarController.next_direction(&game, 0);
+            game.step(std::slice::from_ref(&direction), &mut rng);
+            assert!(!game.is_dead || !has_safe_move, "died with {direction:?} although a safe move existed after {} moves", game.moves);
+        }
+        assert!(game.snakes[0].points > 0);
+    }
+
+    #[test]
+    fn greedy_without_a_path_goes_to_the_biggest_free_space() {
+        // the bird is behind a wall across the board, the cell on the left of the head is a dead end
+        let mut game = game_with_snake(7, 7, &[(1, 5), (1, 4), (1, 3)], Direction::Down);
+        game.level.walls = (0..7).map(|y| Position { x: 3, y }).chain([Position { x: 0, y: 4 }, Position { x: 0, y: 6 }]).collect();
+        add_bird(&mut game, 5, 3);
+        let direction = GreedyAStarController.next_direction(&game, 0).unwrap();
+        assert_ne!(direction, Direction::Left);
+        assert!(is_safe(&game, &direction));
+    }
+
+    #[test]
+    fn greedy_follows_the_tail_only_when_it_moves_away() {
+        // in the corner, the only cell is the tail on the right
+        let mut game = game_with_snake(5, 5, &[(0, 0), (0, 1), (1, 1), (1, 0)], Direction::Up);
+        assert_eq!(GreedyAStarController.next_direction(&game, 0), Some(Direction::Right));
+        assert!(is_safe(&game, &Direction::Right));
+        game.snakes[0].growth = 1;
+        assert_eq!(GreedyAStarController.next_direction(&game, 0), None);
+        assert!(!is_safe(&game, &Direction::Right));
+    }
+
+    #[test]
+    fn greedy_gives_up_when_trapped() {
+        // in the corner, the wall on the left and below, the body on the right
+        let mut game = game_with_snake(5, 5, &[(0, 4), (1, 4), (1, 3), (2, 3)], Direction::Left);
+        game.level.walls = vec![Position { x: 0, y: 3 }];
+        add_bird(&mut game, 4, 0);
+        assert_eq!(GreedyAStarController.next_direction(&game, 0), None);
+    }
+
+    /// every step goes to a neighbour cell and the cycle visits every cell once before it is back at the start
+    fn assert_hamiltonian_cycle(board_width: i32, board_height: i32) {
+        let successors = hamiltonian_cycle(board_width, board_height).unwrap();
+        let cell_count = (board_width * board_height) as usize;
+        assert_eq!(successors.len(), cell_count);
+        let mut is_visited = vec![false; cell_count];
+        let mut cell = 0;
+        for _ in 0..cell_count {
+            assert!(!is_visited[cell], "cell {cell} visited twice on {board_width}x{board_height}");
+            is_visited[cell] = true;
+            let next = successors[cell];
+            let (x, y) = ((cell as i32) % board_width, (cell as i32) / board_width);
+            let (next_x, next_y) = ((next as i32) % board_width, (next as i32) / board_width);
+            assert_eq!((x - next_x).abs() + (y - next_y).abs(), 1, "no neighbour step from {cell} to {next} on {board_width}x{board_height}");
+            cell = next;
+        }
+        assert_eq!(cell, 0);
+    }
+
+    #[test]
+    fn hamiltonian_cycle_on_even_boards() {
+        assert_hamiltonian_cycle(20, 20);
+        assert_hamiltonian_cycle(6, 4);
+        assert_hamiltonian_cycle(2, 2);
+    }
+
+    #[test]
+    fn hamiltonian_cycle_on_boards_with_one_odd_side() {
+        assert_hamiltonian_cycle(5, 4);
+        assert_hamiltonian_cycle(4, 5);
+        assert_hamiltonian_cycle(30, 15);
+    }
+
+    #[test]
+    fn no_hamiltonian_cycle_when_both_sides_are_odd() {
+        assert_eq!(hamiltonian_cycle(5, 5), None);
+        assert_eq!(hamiltonian_cycle(21, 15), None);
+    }
+}
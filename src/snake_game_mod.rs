@@ -0,0 +1,1055 @@
+//! src/snake_game_mod.rs
+//! Pure Rust simulation of the snake game, without Bevy and without javascript.
+//! The Bevy systems in state_in_game_mod are only thin adapters over SnakeGame.
+//! So the game rules can be run and tested on the host with cargo test.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// number of colors a bird can have, the colors are in the bird_colors of the theme
+pub const BIRD_COLOR_COUNT: usize = 9;
+/// more queued turns than this are ignored
+pub const DIRECTION_QUEUE_CAPACITY: usize = 3;
+/// in slow motion the step is this much longer
+pub const SLOW_MOTION_FACTOR: f64 = 2.0;
+/// seconds between two steps at the start of the game, the online game has always this speed
+pub const DEFAULT_STEP_DURATION: f64 = 0.2;
+
+/// GameCoordinates: x goes right, y goes down, (0,0) is top-left
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(PartialEq, Clone, Debug, Deserialize, Serialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// one segment of the snake body, it does not move, only the first and the last segment change
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Segment {
+    pub position: Position,
+    // direction towards the head
+    pub direction: Direction,
+    // direction from the tail
+    pub last_direction: Direction,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snake {
+    pub head: Position,
+    pub direction: Direction,
+    pub last_direction: Direction,
+    pub last_head: Position,
+    /// first segment is after the snake head, the last segment is the tail
+    pub segments: VecDeque<Segment>,
+    pub points: i32,
+    /// the eaten birds, the goal of a level can be a bird count
+    pub birds: i32,
+    /// the segments still to grow, one every step, negative shrinks the snake in the next step
+    pub growth: i32,
+    /// the next bite into the own body is harmless
+    pub is_ghost: bool,
+    /// the steps left in slow motion
+    pub slow_motion_steps: i32,
+    pub is_dead: bool,
+}
+
+/// the birds are the food for the snake
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Food {
+    pub position: Position,
+    /// index into the bird_colors of the theme
+    pub color: usize,
+    /// index into GameRules.food_kinds
+    pub kind: usize,
+    /// the bird flies away at this move, None waits until eaten
+    pub expires_at: Option<i32>,
+    /// the bird waits for a free cell in the bird zones, it is not on the board
+    #[serde(default)]
+    pub is_waiting: bool,
+}
+
+/// A kind of food, the table of the kinds is data in GameRules.food_kinds, from game_config.config.ron.
+/// The default is the classic bird. The image, tint, scale and sound are only for the Bevy view.
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FoodKind {
+    pub name: String,
+    pub points: i32,
+    /// new segments, a negative value shrinks the snake down to one segment
+    pub growth: i32,
+    /// the relative chance of this kind when a bird flies to a new cell, 0 is never
+    pub weight: u32,
+    /// the bird flies away after this many steps, None waits until eaten
+    pub lifetime: Option<i32>,
+    pub power_up: Option<PowerUp>,
+    /// image in the assets folder
+    pub image: String,
+    /// hex color like FFD700 or with alpha F8F8FF80, None is a random color from the theme
+    pub tint: Option<String>,
+    pub scale: f32,
+    /// sound in the assets folder, played when eaten
+    pub sound: String,
+    /// 2.0 is one octave higher
+    pub sound_pitch: f64,
+}
+
+/// the effect of a food on the snake that ate it
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum PowerUp {
+    /// the game steps SLOW_MOTION_FACTOR slower for this many steps
+    SlowMotion(i32),
+    /// the snake passes through itself once
+    Ghost,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum DeathCause {
+    Wall,
+    SelfCollision,
+    /// the head hit the body of the other snake
+    OtherSnake,
+    /// both heads on the same cell
+    HeadOn,
+    /// the player left the online game
+    Disconnected,
+}
+
+/// what happened in one step of the game, snake_index is the index in SnakeGame.snakes
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum GameEvent {
+    /// kind is the eaten kind, the food with food_index is already a new bird
+    Ate {
+        snake_index: usize,
+        food_index: usize,
+        kind: usize,
+    },
+    Grew {
+        snake_index: usize,
+    },
+    Shrank {
+        snake_index: usize,
+    },
+    PoweredUp {
+        snake_index: usize,
+        power_up: PowerUp,
+    },
+    /// the ghost snake passed through itself
+    GhostUsed {
+        snake_index: usize,
+    },
+    /// the timed bird flew away to a new cell before it was eaten
+    FoodExpired {
+        food_index: usize,
+    },
+    /// the waiting bird found a free cell in the bird zones
+    FoodSpawned {
+        food_index: usize,
+    },
+    Died {
+        snake_index: usize,
+        cause: DeathCause,
+    },
+    /// no free cell on the whole board anymore, the player wins
+    BoardCleared,
+    /// the snake reached the goal of the level
+    GoalReached {
+        snake_index: usize,
+    },
+}
+
+/// what a snake must reach to finish a level of the campaign
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum LevelGoal {
+    Points(i32),
+    Birds(i32),
+}
+
+/// what happens when the snake head leaves the board
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub enum Walls {
+    /// the snake dies on the wall
+    Solid,
+    /// no walls, the snake comes back on the opposite edge
+    Wrap,
+}
+
+/// the rules of one game, the default is the classic game
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GameRules {
+    pub board_width: i32,
+    pub board_height: i32,
+    /// head and segments, at least 2
+    pub initial_length: usize,
+    pub bird_count: usize,
+    pub walls: Walls,
+    /// 1 or 2 players
+    pub snake_count: usize,
+    /// the kinds of food, a new game without kinds has the classic bird
+    pub food_kinds: Vec<FoodKind>,
+}
+
+/// a level file has a start for player one and player two
+pub const LEVEL_START_COUNT: usize = 2;
+
+/// Obstacles and starts of a level, the default is the open board.
+/// The Bevy asset with the ASCII grid is in level_mod.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Level {
+    pub name: String,
+    /// the size of the grid, 0 for the open board of any size
+    pub board_width: i32,
+    pub board_height: i32,
+    /// lethal tiles inside the board
+    pub walls: Vec<Position>,
+    /// head position and direction of every snake, without a start the snake is in the middle row going down
+    /// a level file has LEVEL_START_COUNT starts
+    pub starts: Vec<(Position, Direction)>,
+    /// the birds fly only to these cells, empty is everywhere
+    pub bird_zones: Vec<Position>,
+    /// None is an endless level
+    pub goal: Option<LevelGoal>,
+}
+
+/// Small FIFO of the turns pressed between two steps, one turn is consumed every step.
+/// So quick double turns are not lost.
+#[derive(Clone, Debug, Default)]
+pub struct DirectionQueue {
+    queue: VecDeque<Direction>,
+}
+
+/// every random decision of the game goes through this trait
+pub trait RandomSource {
+    /// random number in the range 0.0..1.0
+    fn next_f64(&mut self) -> f64;
+
+    /// random number in the range 0..max
+    fn below(&mut self, max: usize) -> usize {
+        ((self.next_f64() * max as f64) as usize).min(max.saturating_sub(1))
+    }
+}
+
+/// SplitMix64 is tiny, fast and good enough for a game.
+/// The client and the server have the same numbers from the same seed.
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+/// the whole state of the game, the online server sends it to the clients as snapshot
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SnakeGame {
+    pub rules: GameRules,
+    /// one snake for every player
+    pub snakes: Vec<Snake>,
+    pub food: Vec<Food>,
+    pub level: Level,
+    pub moves: i32,
+    /// one of the snakes is dead, the game is over
+    pub is_dead: bool,
+    pub is_cleared: bool,
+    /// a snake reached the goal of the level
+    pub is_goal_reached: bool,
+}
+
+impl Default for GameRules {
+    fn default() -> GameRules {
+        GameRules {
+            board_width: 20,
+            board_height: 20,
+            initial_length: 3,
+            bird_count: 3,
+            walls: Walls::Solid,
+            snake_count: 1,
+            food_kinds: vec![FoodKind::default()],
+        }
+    }
+}
+
+impl Default for FoodKind {
+    /// the classic bird, 1 point and 1 segment
+    fn default() -> FoodKind {
+        FoodKind {
+            name: "bird".to_string(),
+            points: 1,
+            growth: 1,
+            weight: 1,
+            lifetime: None,
+            power_up: None,
+            image: "bird.png".to_string(),
+            tint: None,
+            scale: 1.0,
+            sound: "bird_chirp.mp3".to_string(),
+            sound_pitch: 1.0,
+        }
+    }
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_f64(&mut self) -> f64 {
+        // 53 bits of randomness fit exactly into the f64 mantissa
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl GameRules {
+    /// the position on the opposite edge, if it is outside of the board
+    pub fn wrapped(&self, position: &Position) -> Position {
+        Position {
+            x: position.x.rem_euclid(self.board_width),
+            y: position.y.rem_euclid(self.board_height),
+        }
+    }
+}
+
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+impl DirectionQueue {
+    /// Every turn is validated against the last queued direction, not only against the current direction.
+    /// So a fast double turn cannot reverse the snake into its own neck.
+    /// Returns false if the turn is ignored.
+    pub fn push(&mut self, direction: Direction, current_direction: &Direction) -> bool {
+        let last_direction = self.queue.back().unwrap_or(current_direction);
+        if direction == *last_direction || direction == last_direction.opposite() || self.queue.len() >= DIRECTION_QUEUE_CAPACITY {
+            return false;
+        }
+        self.queue.push_back(direction);
+        true
+    }
+
+    /// the next turn for this step
+    pub fn pop(&mut self) -> Option<Direction> {
+        self.queue.pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// the turn for the next step, without taking it
+    pub fn peek(&self) -> Option<&Direction> {
+        self.queue.front()
+    }
+}
+
+impl Position {
+    /// the neighbour position in this direction
+    pub fn moved(&self, direction: &Direction) -> Position {
+        match direction {
+            Direction::Up => Position { x: self.x, y: self.y - 1 },
+            Direction::Down => Position { x: self.x, y: self.y + 1 },
+            Direction::Left => Position { x: self.x - 1, y: self.y },
+            Direction::Right => Position { x: self.x + 1, y: self.y },
+        }
+    }
+}
+
+impl Level {
+    /// Parse the ASCII grid of a level, every string is one row of the board.
+    /// # is a wall, . is empty, * is a bird zone, ^ v < > is the start of a snake in reading order.
+    /// Every player has a start and the first segments of the snakes do not overlap.
+    pub fn from_grid(name: &str, grid: &[String]) -> Result<Level, String> {
+        let board_height = grid.len() as i32;
+        let board_width = grid.first().map_or(0, |row| row.chars().count()) as i32;
+        if board_width < 5 || board_height < 5 {
+            return Err(format!("Level {name} is smaller than 5x5"));
+        }
+        let mut level = Level {
+            name: name.to_string(),
+            board_width,
+            board_height,
+            ..Level::default()
+        };
+        for (y, row) in grid.iter().enumerate() {
+            if row.chars().count() as i32 != board_width {
+                return Err(format!("Level {name}: row {} has not {board_width} tiles", y + 1));
+            }
+            for (x, tile) in row.chars().enumerate() {
+                let position = Position { x: x as i32, y: y as i32 };
+                match tile {
+                    '#' => level.walls.push(position),
+                    '*' => level.bird_zones.push(position),
+                    '^' => level.starts.push((position, Direction::Up)),
+                    'v' => level.starts.push((position, Direction::Down)),
+                    '<' => level.starts.push((position, Direction::Left)),
+                    '>' => level.starts.push((position, Direction::Right)),
+                    '.' => {}
+                    _ => return Err(format!("Level {name}: unknown tile {tile} in row {}", y + 1)),
+                }
+            }
+        }
+        if level.starts.len() != LEVEL_START_COUNT {
+            return Err(format!("Level {name} has {} starts instead of {LEVEL_START_COUNT}, one for every player", level.starts.len()));
+        }
+        // the body of the snake starts behind the head
+        let mut start_cells: Vec<Position> = level.starts.iter().map(|(head, _)| head.clone()).collect();
+        for (head, direction) in &level.starts {
+            let behind = head.moved(&direction.opposite());
+            if behind.x < 0 || behind.y < 0 || behind.x >= board_width || behind.y >= board_height || level.is_wall(&behind) {
+                return Err(format!("Level {name}: no place for the body behind the start {},{}", head.x, head.y));
+            }
+            if start_cells.contains(&behind) {
+                return Err(format!("Level {name}: the body behind the start {},{} is on another snake", head.x, head.y));
+            }
+            start_cells.push(behind);
+        }
+        Ok(level)
+    }
+
+    pub fn is_wall(&self, position: &Position) -> bool {
+        self.walls.contains(position)
+    }
+}
+
+impl Snake {
+    /// the body is behind the head, as long as it fits before the edge or a wall of the level
+    fn new(rules: &GameRules, level: &Level, head: Position, direction: Direction) -> Snake {
+        let mut segments = VecDeque::new();
+        let mut position = head.clone();
+        for _ in 1..rules.initial_length.max(2) {
+            position = position.moved(&direction.opposite());
+            let is_free = position.x >= 0 && position.y >= 0 && position.x < rules.board_width && position.y < rules.board_height && !level.is_wall(&position);
+            // a snake needs at least one segment
+            if !is_free && !segments.is_empty() {
+                break;
+            }
+            segments.push_back(Segment {
+                position: position.clone(),
+                direction: direction.clone(),
+                last_direction: direction.clone(),
+            });
+        }
+        Snake {
+            head: head.clone(),
+            direction: direction.clone(),
+            last_direction: direction,
+            last_head: head,
+            segments,
+            points: 0,
+            birds: 0,
+            growth: 0,
+            is_ghost: false,
+            slow_motion_steps: 0,
+            is_dead: false,
+        }
+    }
+
+    /// head and segments
+    pub fn length(&self) -> usize {
+        self.segments.len() + 1
+    }
+}
+
+impl SnakeGame {
+    /// New game with the walls and the starts of the level, the board size is from the rules.
+    /// On the open board the snakes are in the middle row of the board going down, evenly spaced.
+    /// The birds wait on random free cells.
+    pub fn new(rules: &GameRules, level: &Level, rng: &mut impl RandomSource) -> SnakeGame {
+        let mut rules = rules.clone();
+        if rules.food_kinds.is_empty() {
+            rules.food_kinds.push(FoodKind::default());
+        }
+        let rules = &rules;
+        let snake_count = rules.snake_count.max(1);
+        let snakes = (0..snake_count)
+            .map(|snake_index| {
+                let (head, direction) = level.starts.get(snake_index).cloned().unwrap_or_else(|| {
+                    let x = rules.board_width * (snake_index as i32 + 1) / (snake_count as i32 + 1);
+                    (Position { x, y: rules.board_height / 2 }, Direction::Down)
+                });
+                Snake::new(rules, level, head, direction)
+            })
+            .collect();
+        let mut game = SnakeGame {
+            rules: rules.clone(),
+            snakes,
+            food: vec![],
+            level: level.clone(),
+            moves: 0,
+            is_dead: false,
+            is_cleared: false,
+            is_goal_reached: false,
+        };
+        for _ in 0..rules.bird_count {
+            let free_cells = game.free_cells();
+            if free_cells.is_empty() {
+                break;
+            }
+            let food = game.random_food(&free_cells, rng);
+            game.food.push(food);
+        }
+        game
+    }
+
+    /// One step of the game: move the heads, eat birds, move segments and check if dead.
+    /// The inputs are the new directions by snake index, a reversal into the own neck is ignored.
+    pub fn step(&mut self, inputs: &[Option<Direction>], rng: &mut impl RandomSource) -> Vec<GameEvent> {
+        let mut events = vec![];
+        if self.is_over() {
+            return events;
+        }
+        for snake_index in 0..self.snakes.len() {
+            self.move_head(snake_index, inputs.get(snake_index).cloned().flatten());
+        }
+        self.moves += 1;
+        let eaten = self.eat_food(&mut events);
+        for snake_index in 0..self.snakes.len() {
+            self.move_segments(snake_index, &mut events);
+        }
+        self.check_dead(&mut events);
+        if !self.is_dead {
+            self.place_waiting_food(rng, &mut events);
+            self.respawn_food(&eaten, rng, &mut events);
+            if !self.is_cleared {
+                self.expire_food(rng, &mut events);
+            }
+            self.check_goal(&mut events);
+        }
+        events
+    }
+
+    /// any snake ate the slow motion, the view makes the steps longer
+    pub fn is_slow_motion(&self) -> bool {
+        self.snakes.iter().any(|snake| snake.slow_motion_steps > 0)
+    }
+
+    pub fn food_kind(&self, kind: usize) -> &FoodKind {
+        &self.rules.food_kinds[kind]
+    }
+
+    /// dead, cleared or the goal of the level is reached
+    pub fn is_over(&self) -> bool {
+        self.is_dead || self.is_cleared || self.is_goal_reached
+    }
+
+    /// the best snake and the target of the level goal, None for an endless level
+    pub fn goal_progress(&self) -> Option<(i32, i32)> {
+        let (value, target) = match self.level.goal.as_ref()? {
+            LevelGoal::Points(target) => (self.snakes.iter().map(|snake| snake.points).max(), target),
+            LevelGoal::Birds(target) => (self.snakes.iter().map(|snake| snake.birds).max(), target),
+        };
+        Some((value.unwrap_or(0), *target))
+    }
+
+    /// The player left the online game: the snake dies and the game is over.
+    /// The other snake is the winner. Only the server calls it.
+    #[allow(dead_code)]
+    pub fn remove_player(&mut self, snake_index: usize) -> Vec<GameEvent> {
+        let Some(snake) = self.snakes.get_mut(snake_index).filter(|snake| !snake.is_dead) else {
+            return vec![];
+        };
+        snake.is_dead = true;
+        self.is_dead = true;
+        vec![GameEvent::Died {
+            snake_index,
+            cause: DeathCause::Disconnected,
+        }]
+    }
+
+    /// the sum of the points of all snakes
+    pub fn points(&self) -> i32 {
+        self.snakes.iter().map(|snake| snake.points).sum()
+    }
+
+    /// With two players the winner is the last snake alive. None is a draw or a single player game.
+    pub fn winner(&self) -> Option<usize> {
+        if self.snakes.len() < 2 {
+            return None;
+        }
+        let mut alive = self.snakes.iter().enumerate().filter(|(_, snake)| !snake.is_dead).map(|(snake_index, _)| snake_index);
+        match (alive.next(), alive.next()) {
+            (Some(snake_index), None) => Some(snake_index),
+            _ => None,
+        }
+    }
+
+    fn move_head(&mut self, snake_index: usize, input: Option<Direction>) {
+        let snake = &mut self.snakes[snake_index];
+        snake.slow_motion_steps = (snake.slow_motion_steps - 1).max(0);
+        snake.last_direction = snake.direction.clone();
+        if let Some(direction) = input.filter(|direction| *direction != snake.direction.opposite()) {
+            snake.direction = direction;
+        }
+        snake.last_head = snake.head.clone();
+        snake.head = snake.head.moved(&snake.direction);
+        if self.rules.walls == Walls::Wrap {
+            snake.head = self.rules.wrapped(&snake.head);
+        }
+    }
+
+    /// food: the points, the growth and the power-up of its kind, returns the food index of the eaten birds
+    fn eat_food(&mut self, events: &mut Vec<GameEvent>) -> Vec<usize> {
+        let mut eaten = vec![];
+        for (food_index, food) in self.food.iter().enumerate().filter(|(_, food)| !food.is_waiting) {
+            // with two heads on the same bird, both snakes die head-on anyway
+            let Some(snake_index) = self.snakes.iter().position(|snake| snake.head == food.position) else {
+                continue;
+            };
+            let food_kind = &self.rules.food_kinds[food.kind];
+            let snake = &mut self.snakes[snake_index];
+            snake.points += food_kind.points;
+            snake.birds += 1;
+            snake.growth += food_kind.growth;
+            eaten.push(food_index);
+            events.push(GameEvent::Ate {
+                snake_index,
+                food_index,
+                kind: food.kind,
+            });
+            let Some(power_up) = &food_kind.power_up else {
+                continue;
+            };
+            match power_up {
+                PowerUp::SlowMotion(steps) => snake.slow_motion_steps = snake.slow_motion_steps.max(*steps),
+                PowerUp::Ghost => snake.is_ghost = true,
+            }
+            events.push(GameEvent::PoweredUp {
+                snake_index,
+                power_up: power_up.clone(),
+            });
+        }
+        eaten
+    }
+
+    /// The eaten birds fly to a new random free cell, never on a snake or on another bird.
+    /// When the whole board has no free cell anymore, the board is cleared and the game is won.
+    /// When only the bird zones are full, the bird waits until a cell of a zone is free.
+    fn respawn_food(&mut self, eaten: &[usize], rng: &mut impl RandomSource, events: &mut Vec<GameEvent>) {
+        for food_index in eaten {
+            if self.relocate_food(*food_index, rng) {
+                continue;
+            }
+            if self.is_board_full() {
+                self.is_cleared = true;
+                events.push(GameEvent::BoardCleared);
+                return;
+            }
+            self.food[*food_index].is_waiting = true;
+        }
+    }
+
+    /// the waiting birds fly to the cells of the zones that the snake has left
+    fn place_waiting_food(&mut self, rng: &mut impl RandomSource, events: &mut Vec<GameEvent>) {
+        let waiting: Vec<usize> = (0..self.food.len()).filter(|food_index| self.food[*food_index].is_waiting).collect();
+        for food_index in waiting {
+            if self.relocate_food(food_index, rng) {
+                events.push(GameEvent::FoodSpawned { food_index });
+            }
+        }
+    }
+
+    /// the timed birds fly away when their time is over, if there is a free cell
+    fn expire_food(&mut self, rng: &mut impl RandomSource, events: &mut Vec<GameEvent>) {
+        let expired: Vec<usize> = (0..self.food.len())
+            .filter(|food_index| !self.food[*food_index].is_waiting && self.food[*food_index].expires_at.is_some_and(|expires_at| self.moves >= expires_at))
+            .collect();
+        for food_index in expired {
+            if self.relocate_food(food_index, rng) {
+                events.push(GameEvent::FoodExpired { food_index });
+            }
+        }
+    }
+
+    /// a new random bird on a random free cell, false if there is no free cell
+    fn relocate_food(&mut self, food_index: usize, rng: &mut impl RandomSource) -> bool {
+        let free_cells = self.free_cells();
+        if free_cells.is_empty() {
+            return false;
+        }
+        self.food[food_index] = self.random_food(&free_cells, rng);
+        true
+    }
+
+    /// the cell and the color are random, the kind is random by weight
+    fn random_food(&self, free_cells: &[Position], rng: &mut impl RandomSource) -> Food {
+        let position = free_cells[rng.below(free_cells.len())].clone();
+        let color = rng.below(BIRD_COLOR_COUNT);
+        let kind = self.random_food_kind(rng);
+        Food {
+            position,
+            color,
+            kind,
+            expires_at: self.rules.food_kinds[kind].lifetime.map(|lifetime| self.moves + lifetime),
+            is_waiting: false,
+        }
+    }
+
+    /// with only one kind there is no random number, so the classic game stays the same
+    fn random_food_kind(&self, rng: &mut impl RandomSource) -> usize {
+        let total_weight: u32 = self.rules.food_kinds.iter().map(|food_kind| food_kind.weight).sum();
+        if self.rules.food_kinds.len() < 2 || total_weight == 0 {
+            return 0;
+        }
+        let mut pick = rng.below(total_weight as usize) as u32;
+        for (kind, food_kind) in self.rules.food_kinds.iter().enumerate() {
+            if pick < food_kind.weight {
+                return kind;
+            }
+            pick -= food_kind.weight;
+        }
+        0
+    }
+
+    /// All the cells of the board that are not occupied by a snake, a bird or a wall.
+    /// With bird zones in the level only the cells of the zones.
+    pub fn free_cells(&self) -> Vec<Position> {
+        let occupied = self.occupied_cells();
+        if !self.level.bird_zones.is_empty() {
+            return self
+                .level
+                .bird_zones
+                .iter()
+                .filter(|position| self.is_inside(position) && !occupied[(position.y * self.rules.board_width + position.x) as usize])
+                .cloned()
+                .collect();
+        }
+
+        let mut free_cells = vec![];
+        for y in 0..self.rules.board_height {
+            for x in 0..self.rules.board_width {
+                if !occupied[(y * self.rules.board_width + x) as usize] {
+                    free_cells.push(Position { x, y });
+                }
+            }
+        }
+        free_cells
+    }
+
+    /// no free cell on the whole board, also outside of the bird zones
+    fn is_board_full(&self) -> bool {
+        self.occupied_cells().iter().all(|occupied| *occupied)
+    }
+
+    /// the cells with a snake, a bird on the board or a wall, by y * board_width + x
+    fn occupied_cells(&self) -> Vec<bool> {
+        let mut occupied = vec![false; (self.rules.board_width * self.rules.board_height) as usize];
+        let mut occupy = |position: &Position| {
+            if self.is_inside(position) {
+                occupied[(position.y * self.rules.board_width + position.x) as usize] = true;
+            }
+        };
+        for snake in &self.snakes {
+            occupy(&snake.head);
+            snake.segments.iter().for_each(|segment| occupy(&segment.position));
+        }
+        self.food.iter().filter(|food| !food.is_waiting).for_each(|food| occupy(&food.position));
+        self.level.walls.iter().for_each(&mut occupy);
+        occupied
+    }
+
+    pub fn is_inside(&self, position: &Position) -> bool {
+        position.x >= 0 && position.y >= 0 && position.x < self.rules.board_width && position.y < self.rules.board_height
+    }
+
+    /// The new first segment is where the head was, the tail is removed if not growing.
+    /// A shrinking snake loses more segments at once, but keeps one.
+    fn move_segments(&mut self, snake_index: usize, events: &mut Vec<GameEvent>) {
+        let snake = &mut self.snakes[snake_index];
+        snake.segments.push_front(Segment {
+            position: snake.last_head.clone(),
+            direction: snake.direction.clone(),
+            last_direction: snake.last_direction.clone(),
+        });
+        if snake.growth > 0 {
+            snake.growth -= 1;
+            events.push(GameEvent::Grew { snake_index });
+            return;
+        }
+        snake.segments.pop_back();
+        if snake.growth < 0 {
+            while snake.growth < 0 && snake.segments.len() > 1 {
+                snake.segments.pop_back();
+                snake.growth += 1;
+            }
+            snake.growth = 0;
+            events.push(GameEvent::Shrank { snake_index });
+        }
+    }
+
+    /// all the snakes have moved before the collisions are checked, so two snakes can die in the same step
+    fn check_dead(&mut self, events: &mut Vec<GameEvent>) {
+        let causes: Vec<Option<DeathCause>> = self.snakes.iter().enumerate().map(|(snake_index, snake)| self.death_cause(snake_index, snake)).collect();
+        for (snake_index, cause) in causes.into_iter().enumerate() {
+            if cause == Some(DeathCause::SelfCollision) && self.snakes[snake_index].is_ghost {
+                self.snakes[snake_index].is_ghost = false;
+                events.push(GameEvent::GhostUsed { snake_index });
+                continue;
+            }
+            if let Some(cause) = cause {
+                self.snakes[snake_index].is_dead = true;
+                self.is_dead = true;
+                events.push(GameEvent::Died { snake_index, cause });
+            }
+        }
+    }
+
+    /// the first snake that reached the goal finishes the level
+    fn check_goal(&mut self, events: &mut Vec<GameEvent>) {
+        let Some(goal) = &self.level.goal else {
+            return;
+        };
+        let snake_index = self.snakes.iter().position(|snake| match goal {
+            LevelGoal::Points(target) => snake.points >= *target,
+            LevelGoal::Birds(target) => snake.birds >= *target,
+        });
+        if let Some(snake_index) = snake_index {
+            self.is_goal_reached = true;
+            events.push(GameEvent::GoalReached { snake_index });
+        }
+    }
+
+    fn death_cause(&self, snake_index: usize, snake: &Snake) -> Option<DeathCause> {
+        let head = &snake.head;
+        let others = || self.snakes.iter().enumerate().filter(move |(other_index, _)| *other_index != snake_index).map(|(_, other)| other);
+        // with Walls::Wrap the head is always inside, the walls of the level are lethal in both modes
+        if !self.is_inside(head) || self.level.is_wall(head) {
+            Some(DeathCause::Wall)
+        } else if snake.segments.iter().any(|segment| segment.position == *head) {
+            Some(DeathCause::SelfCollision)
+        } else if others().any(|other| other.head == *head) {
+            Some(DeathCause::HeadOn)
+        } else if others().any(|other| other.segments.iter().any(|segment| segment.position == *head)) {
+            Some(DeathCause::OtherSnake)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direction_queue_keeps_three_turns() {
+        let mut queue = DirectionQueue::default();
+        assert!(queue.push(Direction::Left, &Direction::Down));
+        assert!(queue.push(Direction::Up, &Direction::Down));
+        assert!(queue.push(Direction::Right, &Direction::Down));
+        assert!(!queue.push(Direction::Down, &Direction::Down));
+        assert_eq!(queue.pop(), Some(Direction::Left));
+        assert_eq!(queue.pop(), Some(Direction::Up));
+        assert_eq!(queue.pop(), Some(Direction::Right));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn direction_queue_rejects_the_reverse_of_the_last_queued_turn() {
+        let mut queue = DirectionQueue::default();
+        assert!(queue.push(Direction::Left, &Direction::Down));
+        // Right is not the reverse of the current Down, but of the queued Left
+        assert!(!queue.push(Direction::Right, &Direction::Down));
+        assert!(!queue.push(Direction::Left, &Direction::Down));
+        assert!(queue.push(Direction::Up, &Direction::Down));
+        assert_eq!(queue.peek(), Some(&Direction::Left));
+    }
+
+    fn grid(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn from_grid_reads_walls_zones_and_starts() {
+        let level = Level::from_grid("test", &grid(&["#####", "#..*#", "#v.^#", "#...#", "#####"])).unwrap();
+        assert_eq!((level.board_width, level.board_height), (5, 5));
+        assert_eq!(level.walls.len(), 16);
+        assert_eq!(level.bird_zones, vec![Position { x: 3, y: 1 }]);
+        assert_eq!(level.starts, vec![(Position { x: 1, y: 2 }, Direction::Down), (Position { x: 3, y: 2 }, Direction::Up)]);
+    }
+
+    #[test]
+    fn from_grid_errors() {
+        let errors = [
+            (vec!["....", "....", "....", "....", "...."], "smaller than 5x5"),
+            (vec![".....", ".v.v.", "....", ".....", "....."], "row 3 has not 5 tiles"),
+            (vec![".....", ".v.v.", "..x..", ".....", "....."], "unknown tile x in row 3"),
+            (vec![".....", ".....", ".....", ".....", "....."], "has 0 starts"),
+            (vec![".....", ".v...", ".....", ".....", "....."], "has 1 starts"),
+            (vec![".....", ".v.v.", "..v..", ".....", "....."], "has 3 starts"),
+            (vec!["v....", ".....", "..v..", ".....", "....."], "no place for the body behind the start 0,0"),
+            (vec![".....", ".#...", ".v.v.", ".....", "....."], "no place for the body behind the start 1,2"),
+            // the body of the first snake is on the head of the second, then the bodies of two snakes on the same cell
+            (vec![".^...", ".v...", ".....", ".....", "....."], "behind the start 1,0 is on another snake"),
+            (vec![".....", ".>...", "v....", ".....", "....."], "behind the start 0,2 is on another snake"),
+        ];
+        for (rows, expected) in errors {
+            let error = Level::from_grid("test", &grid(&rows)).unwrap_err();
+            assert!(error.contains(expected), "{error} does not contain {expected}");
+        }
+    }
+
+    /// the single snake starts in the middle row going down, without birds
+    fn new_game(rules: GameRules) -> SnakeGame {
+        let mut game = SnakeGame::new(&rules, &Level::default(), &mut SplitMix64::new(1));
+        game.food.clear();
+        game
+    }
+
+    #[test]
+    fn step_eats_a_bird_and_grows() {
+        let mut game = new_game(GameRules::default());
+        let snake = &game.snakes[0];
+        game.food.push(Food {
+            position: snake.head.moved(&snake.direction),
+            color: 0,
+            kind: 0,
+            expires_at: None,
+            is_waiting: false,
+        });
+        let length = game.snakes[0].length();
+        let events = game.step(&[None], &mut SplitMix64::new(1));
+        assert_eq!(
+            events,
+            vec![
+                GameEvent::Ate {
+                    snake_index: 0,
+                    food_index: 0,
+                    kind: 0
+                },
+                GameEvent::Grew { snake_index: 0 }
+            ]
+        );
+        assert_eq!(game.snakes[0].points, 1);
+        assert_eq!(game.snakes[0].length(), length + 1);
+        // the bird flew to a free cell
+        assert_ne!(game.food[0].position, game.snakes[0].head);
+    }
+
+    #[test]
+    fn step_dies_on_the_wall() {
+        let mut game = new_game(GameRules::default());
+        let mut events = vec![];
+        while !game.is_over() {
+            events = game.step(&[None], &mut SplitMix64::new(1));
+        }
+        assert_eq!(game.snakes[0].head.y, game.rules.board_height);
+        assert_eq!(
+            events,
+            vec![GameEvent::Died {
+                snake_index: 0,
+                cause: DeathCause::Wall
+            }]
+        );
+    }
+
+    #[test]
+    fn step_dies_on_the_body() {
+        let mut game = new_game(GameRules {
+            initial_length: 5,
+            ..GameRules::default()
+        });
+        assert!(game.step(&[Some(Direction::Right)], &mut SplitMix64::new(1)).is_empty());
+        assert!(game.step(&[Some(Direction::Up)], &mut SplitMix64::new(1)).is_empty());
+        let events = game.step(&[Some(Direction::Left)], &mut SplitMix64::new(1));
+        assert_eq!(
+            events,
+            vec![GameEvent::Died {
+                snake_index: 0,
+                cause: DeathCause::SelfCollision
+            }]
+        );
+        assert!(game.is_dead);
+    }
+
+    /// a snake on a board of one row, the head at x going right, one segment behind and a bird in front
+    fn new_row_game(board_width: i32, x: i32) -> SnakeGame {
+        let mut game = new_game(GameRules {
+            board_width,
+            board_height: 1,
+            ..GameRules::default()
+        });
+        let snake = &mut game.snakes[0];
+        snake.head = Position { x, y: 0 };
+        snake.direction = Direction::Right;
+        snake.segments = VecDeque::from([Segment {
+            position: Position { x: x - 1, y: 0 },
+            direction: Direction::Right,
+            last_direction: Direction::Right,
+        }]);
+        game.food.push(Food {
+            position: Position { x: x + 1, y: 0 },
+            color: 0,
+            kind: 0,
+            expires_at: None,
+            is_waiting: false,
+        });
+        game
+    }
+
+    #[test]
+    fn board_cleared_when_the_whole_board_is_full() {
+        let mut game = new_row_game(3, 1);
+        let events = game.step(&[None], &mut SplitMix64::new(1));
+        assert_eq!(events.last(), Some(&GameEvent::BoardCleared));
+        assert!(game.is_cleared);
+    }
+
+    #[test]
+    fn bird_waits_when_only_the_zone_is_full() {
+        let mut game = new_row_game(5, 1);
+        // the only cell of the zone is under the tail
+        game.level.bird_zones = vec![Position { x: 0, y: 0 }];
+        let events = game.step(&[None], &mut SplitMix64::new(1));
+        assert!(!events.contains(&GameEvent::BoardCleared));
+        assert!(!game.is_over());
+        assert!(game.food[0].is_waiting);
+        // the tail leaves the zone cell in the next step
+        let events = game.step(&[None], &mut SplitMix64::new(1));
+        assert_eq!(events, vec![GameEvent::FoodSpawned { food_index: 0 }]);
+        assert!(!game.food[0].is_waiting);
+        assert_eq!(game.food[0].position, Position { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_the_same_events() {
+        let inputs = [Some(Direction::Left), None, Some(Direction::Down), None, None, Some(Direction::Right), Some(Direction::Up), None];
+        let play = || {
+            let mut rng = SplitMix64::new(42);
+            let rules = GameRules {
+                walls: Walls::Wrap,
+                bird_count: 20,
+                ..GameRules::default()
+            };
+            let mut game = SnakeGame::new(&rules, &Level::default(), &mut rng);
+            let mut all_events = vec![];
+            for input in inputs.iter().cycle().take(200) {
+                all_events.push(game.step(std::slice::from_ref(input), &mut rng));
+            }
+            all_events
+        };
+        let events = play();
+        assert!(events.iter().flatten().any(|event| matches!(event, GameEvent::Ate { .. })));
+        assert_eq!(events, play());
+    }
+}
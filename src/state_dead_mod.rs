@@ -2,14 +2,24 @@
 
 use std::f32::consts::PI;
 
-use bevy::color::palettes::css::{GREEN, RED, WHITE, YELLOW};
-use bevy::{color::Color::Srgba, prelude::*};
+use bevy::prelude::*;
 
-use crate::{AppState, GameBoardCanvas, Orientation};
+use crate::{
+    AppState, GameBoardCanvas, Orientation,
+    game_assets_mod::GameAssets,
+    game_config_mod::GameConfig,
+    high_score_mod::{HighScores, spawn_high_score_table},
+    input_intent_mod::Intent,
+    replay_mod::{GameRecording, start_replay},
+    snake_game_mod::DeathCause,
+    state_in_game_mod::{GameOver, GameOverCause},
+    theme_mod::Theme,
+};
 
 #[derive(Component, PartialEq)]
 enum ButtonEnum {
     KeyN,
+    WatchReplay,
 }
 
 pub fn add_dead_to_app(app: &mut App) {
@@ -27,7 +37,14 @@ pub fn add_dead_to_app(app: &mut App) {
     );
 }
 
-pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCanvas>, asset_server: Res<AssetServer>) {
+pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCanvas>, game_assets: Res<GameAssets>, theme: Res<Theme>, game_over: Option<Res<GameOver>>, high_scores: Res<HighScores>) {
+    // the online games are not recorded, there is nothing to replay
+    let can_replay = !game_over.as_ref().is_some_and(|game_over| game_over.is_online);
+    let message = match game_over {
+        Some(game_over) => final_stats_text(&game_over, high_scores.last_rank),
+        None => "Snake is dead.".to_string(),
+    };
+
     commands.spawn(Camera2d);
 
     let mut client = if game_board_canvas.orientation == Orientation::Landscape {
@@ -52,7 +69,7 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
             Outline {
                 width: Val::Px(1.),
                 offset: Val::Px(0.),
-                color: WHITE.into(),
+                color: theme.board_color(),
             },
         ));
 
@@ -69,12 +86,9 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
                     // Header
                     header_box.spawn((
                         Text::new("github.com/bestia-dev/snake_bevy_wasm"),
-                        TextFont {
-                            font_size: game_board_canvas.sprite_height,
-                            ..default()
-                        },
+                        game_assets.text_font(game_board_canvas.sprite_height),
                         TextLayout::new_with_justify(JustifyText::Center),
-                        TextColor::from(Srgba(GREEN).with_alpha(0.7)),
+                        TextColor(theme.title_color().with_alpha(0.7)),
                     ));
                 });
             }
@@ -89,14 +103,12 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
                 middle_box.with_children(|middle_box| {
                     // middle
                     middle_box.spawn((
-                        Text::new("Snake is dead."),
-                        TextFont {
-                            font_size: game_board_canvas.sprite_height,
-                            ..default()
-                        },
+                        Text::new(message),
+                        game_assets.text_font(game_board_canvas.sprite_height),
                         TextLayout::new_with_justify(JustifyText::Center),
-                        TextColor::from(Srgba(YELLOW).with_alpha(0.7)),
+                        TextColor(theme.text_color().with_alpha(0.7)),
                     ));
+                    spawn_high_score_table(middle_box, &game_board_canvas, &game_assets, &theme, &high_scores, true);
                 });
             }
             // footer
@@ -111,13 +123,40 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
                     // footer
                     footer_box.spawn((
                         Text::new("Press N to start"),
-                        TextFont {
-                            font_size: game_board_canvas.sprite_height,
-                            ..default()
-                        },
+                        game_assets.text_font(game_board_canvas.sprite_height),
                         TextLayout::new_with_justify(JustifyText::Center),
-                        TextColor::from(Srgba(RED).with_alpha(0.7)),
+                        TextColor(theme.hint_color().with_alpha(0.7)),
                     ));
+                    if can_replay {
+                        footer_box
+                            .spawn((
+                                Button,
+                                ButtonEnum::WatchReplay,
+                                Interaction::None,
+                                Node {
+                                    padding: UiRect::all(Val::Px(5.0)),
+                                    ..default()
+                                },
+                                Outline {
+                                    width: Val::Px(1.),
+                                    offset: Val::Px(0.),
+                                    color: theme.text_color(),
+                                },
+                            ))
+                            .with_children(|button| {
+                                button.spawn((
+                                    Text::new("Press R to watch the last game"),
+                                    game_assets.text_font(game_board_canvas.sprite_height * 0.7),
+                                    TextColor(theme.text_color()),
+                                ));
+                            });
+                        footer_box.spawn((
+                            Text::new("The url in the address bar is the link to this replay"),
+                            game_assets.text_font(game_board_canvas.sprite_height * 0.5),
+                            TextLayout::new_with_justify(JustifyText::Center),
+                            TextColor(theme.hint_color().with_alpha(0.7)),
+                        ));
+                    }
                 });
             }
         });
@@ -145,7 +184,7 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
                 Outline {
                     width: Val::Px(1.),
                     offset: Val::Px(-2.),
-                    color: WHITE.into(),
+                    color: theme.board_color(),
                 },
             ));
             keys.with_children(|keys| {
@@ -165,7 +204,7 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
                     Button,
                     ButtonEnum::KeyN,
                     ImageNode {
-                        image: asset_server.load("key_n.png"),
+                        image: game_assets.key_n.clone(),
                         ..default()
                     },
                     Interaction::None,
@@ -184,6 +223,45 @@ pub fn on_enter_dead(mut commands: Commands, game_board_canvas: Res<GameBoardCan
     });
 }
 
+/// the cause of death, the final numbers and the high score rank
+fn final_stats_text(game_over: &GameOver, rank: Option<usize>) -> String {
+    let cause = match game_over.cause {
+        GameOverCause::Died(DeathCause::Wall) => "Snake hit the wall.",
+        GameOverCause::Died(DeathCause::SelfCollision) => "Snake bit itself.",
+        GameOverCause::Died(DeathCause::OtherSnake) => "Snake hit the other snake.",
+        GameOverCause::Died(DeathCause::HeadOn) => "Head-on collision.",
+        GameOverCause::Died(DeathCause::Disconnected) => "The other player left.",
+        GameOverCause::BoardCleared => "Board cleared. You win!",
+        GameOverCause::GoalReached(_) => "Level complete.",
+        GameOverCause::ConnectionLost => "The connection to the server is lost.",
+    };
+    // with two players the winner is the last snake alive
+    let cause = match (game_over.snake_points.as_slice(), game_over.winner) {
+        ([_], _) => cause.to_string(),
+        (snake_points, winner) => {
+            let winner = match winner {
+                Some(snake_index) => format!("Player {} wins!", snake_index + 1),
+                None => "Draw!".to_string(),
+            };
+            let points: Vec<String> = snake_points.iter().enumerate().map(|(snake_index, points)| format!("Player {}: {points}", snake_index + 1)).collect();
+            format!("{cause} {winner}\n{}", points.join(" "))
+        }
+    };
+    let rank = match rank {
+        _ if game_over.is_replay => "Replay".to_string(),
+        _ if game_over.is_online => "Online game".to_string(),
+        Some(rank) => format!("High score rank: {}", rank + 1),
+        None => "Not in the high scores".to_string(),
+    };
+    format!(
+        "{cause}\nPoints: {} Moves: {} Length: {}\nTime survived: {:.1}s\n{rank}",
+        game_over.points,
+        game_over.moves,
+        game_over.length,
+        game_over.survived.as_secs_f32()
+    )
+}
+
 // despawn all entities
 pub fn on_exit_dead(mut commands: Commands, entities: Query<Entity, With<Visibility>>) {
     for entity in entities {
@@ -191,17 +269,38 @@ pub fn on_exit_dead(mut commands: Commands, entities: Query<Entity, With<Visibil
     }
 }
 
-pub fn handle_dead_ui_input(keys: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
-    if keys.pressed(KeyCode::KeyN) {
-        next_state.set(AppState::InGame);
+/// the N key or a tap anywhere is the Start intent, the R key is the Replay intent
+/// only the first of them is used, there is no replay of an online game
+pub fn handle_dead_ui_input(
+    mut commands: Commands,
+    mut intents: EventReader<Intent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut game_config: ResMut<GameConfig>,
+    game_recording: Option<Res<GameRecording>>,
+    game_over: Option<Res<GameOver>>,
+) {
+    match intents.read().find(|intent| matches!(intent, Intent::Start | Intent::Replay)) {
+        Some(Intent::Start) => next_state.set(AppState::InGame),
+        Some(Intent::Replay) => {
+            // after an online game the recording is still the one of the last local game
+            let is_online = game_over.is_some_and(|game_over| game_over.is_online);
+            if let Some(game_recording) = game_recording.filter(|_| !is_online) {
+                start_replay(&mut commands, &mut game_config, &mut next_state, game_recording.0.clone());
+            }
+        }
+        _ => {}
     }
 }
 
+/// the on-screen buttons send the same intents as the keyboard
 #[allow(clippy::type_complexity)]
-fn button_interaction_system(interaction_query: Query<(&ButtonEnum, &Interaction), (Changed<Interaction>, With<Button>)>, mut next_state: ResMut<NextState<AppState>>) {
+fn button_interaction_system(interaction_query: Query<(&ButtonEnum, &Interaction), (Changed<Interaction>, With<Button>)>, mut intents: EventWriter<Intent>) {
     for interaction in interaction_query {
-        if *interaction.0 == ButtonEnum::KeyN && *interaction.1 == Interaction::Pressed {
-            next_state.set(AppState::InGame);
+        if *interaction.1 == Interaction::Pressed {
+            intents.write(match interaction.0 {
+                ButtonEnum::KeyN => Intent::Start,
+                ButtonEnum::WatchReplay => Intent::Replay,
+            });
         }
     }
 }
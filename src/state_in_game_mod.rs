@@ -1,18 +1,30 @@
 // state_in_game_mod.rs
 
 use std::f32::consts::PI;
+use std::time::Duration;
 
-use bevy::{
-    color::palettes::css::{GREEN, RED, WHITE},
-    prelude::*,
-};
+use bevy::prelude::*;
 use bevy_kira_audio::{AudioControl, AudioInstance, AudioTween};
 
-use crate::{AppState, GameBoardCanvas, Orientation};
+use crate::{
+    AppState, GameBoardCanvas, InGameState, Orientation,
+    autopilot_mod::Autopilot,
+    game_assets_mod::{GameAssets, SnakePart},
+    game_config_mod::GameConfig,
+    game_rng_mod::GameRng,
+    level_mod::Levels,
+    on_game_board,
+    online_mod::OnlineSession,
+    replay_mod::{GameRecording, Recording, ReplayPlayback},
+    snake_game_mod::{DeathCause, Direction, DirectionQueue, GameEvent, Position, Segment, SnakeGame},
+    theme_mod::Theme,
+};
 mod state_in_game_events_mod;
 use state_in_game_events_mod::*;
 mod state_in_game_logic_mod;
 use state_in_game_logic_mod::*;
+// the attract mode colors the food like the game
+pub use state_in_game_logic_mod::food_color;
 mod state_in_game_render_mod;
 use state_in_game_render_mod::*;
 
@@ -24,39 +36,72 @@ struct DebugText {
 #[derive(Component)]
 struct PointsText {}
 
+// the game logic is in SnakeGame, the components are only the view of it
+#[derive(Resource)]
+pub struct SnakeGameRes(pub SnakeGame);
+
+// the events of the last SnakeGame::step()
+#[derive(Resource, Default)]
+struct StepEvents(Vec<GameEvent>);
+
+// the game time without the pauses, the sum of all the fixed steps
+#[derive(Resource, Default)]
+struct GameClock(Duration);
+
+/// the final result of the game, check_dead inserts it and the Dead state shows it
+#[derive(Resource, Clone, Debug)]
+pub struct GameOver {
+    pub cause: GameOverCause,
+    /// the sum of the points of all snakes
+    pub points: i32,
+    pub moves: i32,
+    /// head and segments of the longest snake
+    pub length: usize,
+    /// the points by snake index, only one for a single player game
+    pub snake_points: Vec<i32>,
+    /// the snake index of the winner of a two player game, None for a draw
+    pub winner: Option<usize>,
+    pub survived: Duration,
+    /// a replay is not a new result for the high scores
+    pub is_replay: bool,
+    /// the online games are not in the local high scores
+    pub is_online: bool,
+}
+
 #[derive(Clone, PartialEq, Debug)]
-struct Position {
-    x: i32,
-    y: i32,
+pub enum GameOverCause {
+    Died(DeathCause),
+    BoardCleared,
+    /// the snake with this index reached the goal of the level
+    GoalReached(usize),
+    /// the online server closed the connection
+    ConnectionLost,
 }
 
-// only one bird at any time
+// bird_count birds at any time, index is the same as in SnakeGame.food
 #[derive(Component)]
 pub struct Bird {
+    index: usize,
     position: Position,
     color: Color,
+    /// index into GameRules.food_kinds, for the image and the scale
+    kind: usize,
+    /// hidden while the bird waits for a free cell in the bird zones
+    is_waiting: bool,
 }
 
-#[derive(PartialEq, Clone, Debug)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
-
-// only one snake_head
+// one snake_head for every player
 #[derive(Component)]
 struct SnakeHead {
+    // index in SnakeGame.snakes
+    snake_index: usize,
     position: Position,
     direction: Direction,
+    // the cell of the head before the last step, the render interpolates from there
+    from: Segment,
     // keyboard events are too often to have game logic inside
-    new_direction: Direction,
-    last_direction: Direction,
-    last_position: Position,
-    just_eating: bool,
-    moves: i32,
-    points: i32,
+    // the turns wait in the queue for the next step
+    direction_queue: DirectionQueue,
 }
 
 // one component can spawn many entities
@@ -66,76 +111,106 @@ struct SnakeSegment {
     direction: Direction,
     last_direction: Direction,
     is_tail: bool,
+    // the cell of this segment before the last step, the render interpolates from there
+    from: Segment,
 }
 
 #[derive(Component, Clone, Debug)]
 struct SnakeSegmentIndex {
+    snake_index: usize,
     index: usize,
 }
 
-// Component to identify the audio entity
-#[derive(Component)]
-struct Music;
-
-// Marker struct to help identify the color-changing Text component
-#[derive(Component)]
-struct AnimatedText;
-
 #[derive(Resource)]
-struct InstanceHandle(Handle<AudioInstance>);
+pub struct InstanceHandle(pub Handle<AudioInstance>);
 
+// the buttons are named after the keys they replace
+#[allow(clippy::enum_variant_names)]
 #[derive(Component, PartialEq)]
 enum ButtonEnum {
     KeyUp,
     KeyRight,
     KeyDown,
     KeyLeft,
-    KeyX,
 }
 
-const STEP_DURATION: f64 = 0.2;
-
 const SNAKE_Z_LAYER: f32 = 2.0;
 const BIRD_Z_LAYER: f32 = 1.0;
 const OTHER_Z_LAYER: f32 = 0.0;
 
 pub fn add_in_game_to_app(app: &mut App) {
-    // Set the Fixed Timestep interval for game logic to 0.x seconds
-    app.insert_resource(Time::<Fixed>::from_seconds(STEP_DURATION));
+    // Set the Fixed Timestep interval for game logic to 0.x seconds, on_enter_in_game sets it from GameConfig
+    app.insert_resource(Time::<Fixed>::from_seconds(GameConfig::default().step_duration));
+    app.init_resource::<StepEvents>();
+    app.init_resource::<GameClock>();
     app.add_systems(OnEnter(AppState::InGame), on_enter_in_game);
     app.add_systems(OnExit(AppState::InGame), on_exit_in_game);
+    app.add_systems(OnEnter(AppState::Replay), on_enter_in_game);
+    app.add_systems(OnExit(AppState::Replay), on_exit_in_game);
+    app.add_systems(OnEnter(AppState::Online), on_enter_in_game);
+    app.add_systems(OnExit(AppState::Online), on_exit_in_game);
+    // the game logic is frozen while paused
     app.add_systems(
         FixedUpdate,
         (
-            move_snake_head.run_if(in_state(AppState::InGame)),
-            eat_bird.run_if(in_state(AppState::InGame)),
-            move_segments.run_if(in_state(AppState::InGame)),
-            check_dead.run_if(in_state(AppState::InGame)),
-            render_snake_head.run_if(in_state(AppState::InGame)),
-            render_segment.run_if(in_state(AppState::InGame)),
+            tick_game_clock.run_if(in_state(InGameState::Running)),
+            steer_with_autopilot.run_if(in_state(InGameState::Running).and(resource_exists::<Autopilot>)),
+            move_snake_head.run_if(in_state(InGameState::Running).and(not(in_state(AppState::Online)))),
+            apply_online_snapshot.run_if(in_state(InGameState::Running).and(in_state(AppState::Online))),
+            eat_bird.run_if(in_state(InGameState::Running)),
+            move_segments.run_if(in_state(InGameState::Running)),
+            check_dead.run_if(in_state(InGameState::Running)),
         )
             .chain(),
     );
 
     app.add_systems(
         // render frame and react to events
+        // the replay and the online game use the same systems, only the input is from the recording or from the server
         Update,
         (
-            crate::handle_browser_resize.run_if(in_state(AppState::InGame)),
-            button_interaction_system.run_if(in_state(AppState::InGame)),
+            crate::handle_browser_resize.run_if(on_game_board),
+            button_interaction_system.run_if(on_game_board),
             // draw_axis.run_if(in_state(AppState::InGame)),
-            render_bird.run_if(in_state(AppState::InGame)),
+            // between two fixed steps the snake moves smoothly, paused it stands still
+            (render_snake_head, render_segment).run_if(in_state(InGameState::Running)),
+            (render_bird, blink_expiring_birds).chain().run_if(on_game_board),
+            render_ghost_snake.run_if(on_game_board),
             handle_movement_input.run_if(in_state(AppState::InGame)),
-            render_points_text.run_if(in_state(AppState::InGame)),
-            render_debug_text.run_if(in_state(AppState::InGame)),
+            render_points_text.run_if(on_game_board),
+            // the online server has its own speed
+            speed_up_snake.run_if(on_game_board.and(not(in_state(AppState::Online)))),
+            render_debug_text.run_if(on_game_board),
         ),
     );
 }
 
 // run on enter in state in_game
-fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audio: Res<bevy_kira_audio::Audio>, game_board_canvas: Res<GameBoardCanvas>) {
-    let handle = audio.play(asset_server.load("snake_hiss.mp3")).looped().handle();
+#[allow(clippy::too_many_arguments)]
+fn on_enter_in_game(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    theme: Res<Theme>,
+    audio: Res<bevy_kira_audio::Audio>,
+    game_board_canvas: Res<GameBoardCanvas>,
+    game_config: Res<GameConfig>,
+    mut time: ResMut<Time<Fixed>>,
+    app_state: Res<State<AppState>>,
+    playback: Option<Res<ReplayPlayback>>,
+    online_session: Option<Res<OnlineSession>>,
+    levels: Res<Levels>,
+) {
+    let handle = audio.play(game_assets.snake_hiss.clone()).looped().handle();
     commands.insert_resource(InstanceHandle(handle));
+    commands.insert_resource(StepEvents::default());
+    commands.insert_resource(GameClock::default());
+    commands.remove_resource::<GameOver>();
+
+    // the difficulty curve starts again from zero points, the online game steps with the server
+    match &online_session {
+        Some(online_session) => time.set_timestep_seconds(online_session.step_duration),
+        None => time.set_timestep_seconds(game_config.step_duration_for_points(0)),
+    }
 
     commands.spawn(Camera2d);
 
@@ -158,7 +233,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
             Outline {
                 width: Val::Px(2.),
                 offset: Val::Px(-1.),
-                color: WHITE.into(),
+                color: theme.board_color(),
             },
         ));
         game_canvas.with_children(|game_canvas| {
@@ -179,7 +254,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
             let mut keys = client.spawn((
                 // the UI buttons will despawn, but the rest of the game
                 // will remain in the background after death
-                StateScoped(AppState::InGame),
+                StateScoped(*app_state.get()),
                 Node {
                     // Use the CSS Grid algorithm for laying out this node
                     display: Display::Grid,
@@ -202,7 +277,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
                 Outline {
                     width: Val::Px(1.),
                     offset: Val::Px(-2.),
-                    color: WHITE.into(),
+                    color: theme.board_color(),
                 },
             ));
 
@@ -223,7 +298,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
                     Button,
                     ButtonEnum::KeyUp,
                     ImageNode {
-                        image: asset_server.load("key_up.png"),
+                        image: game_assets.key_up.clone(),
                         ..default()
                     },
                     Interaction::None,
@@ -257,7 +332,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
                     Button,
                     ButtonEnum::KeyLeft,
                     ImageNode {
-                        image: asset_server.load("key_left.png"),
+                        image: game_assets.key_left.clone(),
                         ..default()
                     },
                     Interaction::None,
@@ -277,7 +352,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
                     Button,
                     ButtonEnum::KeyDown,
                     ImageNode {
-                        image: asset_server.load("key_down.png"),
+                        image: game_assets.key_down.clone(),
                         ..default()
                     },
                     Interaction::None,
@@ -297,7 +372,7 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
                     Button,
                     ButtonEnum::KeyRight,
                     ImageNode {
-                        image: asset_server.load("key_right.png"),
+                        image: game_assets.key_right.clone(),
                         ..default()
                     },
                     Interaction::None,
@@ -316,81 +391,83 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
         }
     });
 
-    // snake head
-    let snake_head_position = Position { x: 10, y: 10 };
-    commands.spawn((
-        Sprite::from_image(asset_server.load("snake_head_left.png")),
-        Transform::from_xyz(snake_head_position.to_bevy_x(&game_board_canvas), snake_head_position.to_bevy_y(&game_board_canvas), SNAKE_Z_LAYER).with_rotation(Quat::from_rotation_z(PI * 0.5)),
-        SnakeHead {
-            position: snake_head_position.clone(),
-            direction: Direction::Down,
-            new_direction: Direction::Down,
-            last_direction: Direction::Down,
-            last_position: snake_head_position,
-            just_eating: false,
-            moves: 0,
-            points: 0,
-        },
-    ));
-
-    // first (zero) segment
-    let segment_position = Position { x: 10, y: 9 };
-    commands.spawn((
-        Sprite::from_image(asset_server.load("segment_horizontal.png")),
-        Transform::from_xyz(segment_position.to_bevy_x(&game_board_canvas), segment_position.to_bevy_y(&game_board_canvas), OTHER_Z_LAYER).with_rotation(Quat::from_rotation_z(PI * 0.5)),
-        SnakeSegment {
-            position: segment_position,
-            direction: Direction::Down,
-            last_direction: Direction::Down,
-            is_tail: false,
-        },
-        SnakeSegmentIndex { index: 0 },
-    ));
-
-    // last (1) segment
-    let segment_position = Position { x: 10, y: 8 };
-    commands.spawn((
-        Sprite::from_image(asset_server.load("segment_tail.png")),
-        Transform::from_xyz(segment_position.to_bevy_x(&game_board_canvas), segment_position.to_bevy_y(&game_board_canvas), OTHER_Z_LAYER).with_rotation(Quat::from_rotation_z(PI * 0.5)),
-        SnakeSegment {
-            position: segment_position,
-            direction: Direction::Down,
-            last_direction: Direction::Down,
-            is_tail: true,
-        },
-        SnakeSegmentIndex { index: 1 },
-    ));
+    // a new seed for every game, unless it is fixed in the url
+    // the replay uses the recorded seed, the GameConfig is already replaced with the recorded config
+    let mut game_rng = match &playback {
+        Some(playback) => GameRng::new(playback.recording().seed),
+        None => GameRng::from_url_or_clock(),
+    };
+    info!("game seed {}", game_rng.seed());
+    // the online game starts with the first state from the server
+    let game = match &online_session {
+        Some(online_session) => online_session.initial_game.clone(),
+        None => {
+            if !game_config.level.is_empty() && levels.find(&game_config.level).is_none() {
+                warn!("the level {} is not loaded, the game is on the open board", game_config.level);
+            }
+            SnakeGame::new(&game_config.rules, &levels.level_for(&game_config), &mut game_rng)
+        }
+    };
+    if playback.is_none() {
+        // the old replay link is not valid for the new game
+        crate::wsm::set_url_fragment("");
+    }
+    match (&playback, &online_session) {
+        (Some(playback), _) => commands.insert_resource(GameRecording(playback.recording().clone())),
+        (None, None) => commands.insert_resource(GameRecording(Recording::new(game_rng.seed(), &game_config))),
+        // the random numbers of the online game are on the server, it cannot be replayed
+        (None, Some(_)) => commands.remove_resource::<GameRecording>(),
+    }
+    commands.insert_resource(game_rng);
+
+    // snake heads and segments, the theme tints every snake
+    for (snake_index, snake) in game.snakes.iter().enumerate() {
+        commands.spawn((
+            Sprite {
+                color: theme.snake_tint(snake_index),
+                ..game_assets.snake_sprite(SnakePart::Head)
+            },
+            Transform::from_xyz(snake.head.to_bevy_x(&game_board_canvas), snake.head.to_bevy_y(&game_board_canvas), SNAKE_Z_LAYER).with_rotation(Quat::from_rotation_z(PI * 0.5)),
+            SnakeHead {
+                snake_index,
+                position: snake.head.clone(),
+                direction: snake.direction.clone(),
+                from: Segment {
+                    position: snake.head.clone(),
+                    direction: snake.direction.clone(),
+                    last_direction: snake.direction.clone(),
+                },
+                direction_queue: DirectionQueue::default(),
+            },
+        ));
 
-    // spawn 3 entity bird
-    let bird_position = Position { x: 2, y: 3 };
-    commands.spawn((
-        Sprite::from_image(asset_server.load("bird.png")),
-        Transform::from_xyz(bird_position.to_bevy_x(&game_board_canvas), bird_position.to_bevy_y(&game_board_canvas), BIRD_Z_LAYER),
-        Bird {
-            position: bird_position.clone(),
-            color: RED.into(),
-        },
-    ));
+        for (index, segment) in snake.segments.iter().enumerate() {
+            spawn_segment(&mut commands, &game_assets, &theme, &game_board_canvas, segment, snake_index, index, index == snake.segments.len() - 1);
+        }
+    }
 
-    let bird_position = Position { x: 9, y: 9 };
-    commands.spawn((
-        Sprite::from_image(asset_server.load("bird.png")),
-        Transform::from_xyz(bird_position.to_bevy_x(&game_board_canvas), bird_position.to_bevy_y(&game_board_canvas), BIRD_Z_LAYER).with_rotation(Quat::from_rotation_z(-PI * 0.25)),
-        Bird {
-            position: bird_position.clone(),
-            color: WHITE.into(),
-        },
-    ));
+    // the walls of the level never move
+    for wall in &game.level.walls {
+        commands.spawn((
+            Sprite::from_color(theme.wall_color(), Vec2::new(game_board_canvas.sprite_width, game_board_canvas.sprite_height)),
+            Transform::from_xyz(wall.to_bevy_x(&game_board_canvas), wall.to_bevy_y(&game_board_canvas), OTHER_Z_LAYER),
+        ));
+    }
 
-    let bird_position = Position { x: 14, y: 14 };
-    commands.spawn((
-        Sprite::from_image(asset_server.load("bird.png")),
-        Transform::from_xyz(bird_position.to_bevy_x(&game_board_canvas), bird_position.to_bevy_y(&game_board_canvas), BIRD_Z_LAYER).with_rotation(Quat::from_rotation_z(-PI * 0.5)),
-        Bird {
-            position: bird_position.clone(),
-            color: GREEN.into(),
-        },
-    ));
+    // spawn the bird entities, each one rotated a little more
+    for (index, food) in game.food.iter().enumerate() {
+        commands.spawn((
+            Sprite::from_image(game_assets.food_image(game.food_kind(food.kind))),
+            Transform::from_xyz(food.position.to_bevy_x(&game_board_canvas), food.position.to_bevy_y(&game_board_canvas), BIRD_Z_LAYER).with_rotation(Quat::from_rotation_z(-PI * 0.25 * index as f32)),
+            Bird {
+                index,
+                position: food.position.clone(),
+                color: food_color(food, game.food_kind(food.kind), &theme),
+                kind: food.kind,
+                is_waiting: food.is_waiting,
+            },
+        ));
+    }
 
     // DebugText
     commands.spawn((
@@ -403,9 +480,11 @@ fn on_enter_in_game(mut commands: Commands, asset_server: Res<AssetServer>, audi
             ..default()
         },
         DebugText {
-            bird_position: format!("{:?}", &bird_position),
+            bird_position: format!("{:?}", game.food.last().map(|food| &food.position)),
         },
     ));
+
+    commands.insert_resource(SnakeGameRes(game));
 }
 
 fn on_exit_in_game(handle: Res<InstanceHandle>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
@@ -415,6 +494,27 @@ fn on_exit_in_game(handle: Res<InstanceHandle>, mut audio_instances: ResMut<Asse
     }
 }
 
+impl SnakeSegment {
+    fn from_segment(segment: &Segment, is_tail: bool) -> SnakeSegment {
+        SnakeSegment {
+            position: segment.position.clone(),
+            direction: segment.direction.clone(),
+            last_direction: segment.last_direction.clone(),
+            is_tail,
+            from: segment.clone(),
+        }
+    }
+
+    /// the cell of the segment now
+    fn cell(&self) -> Segment {
+        Segment {
+            position: self.position.clone(),
+            direction: self.direction.clone(),
+            last_direction: self.last_direction.clone(),
+        }
+    }
+}
+
 impl Position {
     /// transform GameCoordinates to BevyCoordinates
     pub fn to_bevy_x(&self, game_board_canvas: &GameBoardCanvas) -> f32 {
@@ -1,43 +1,60 @@
 use bevy::prelude::*;
 
 use crate::{
-    AppState,
-    state_in_game_mod::{ButtonEnum, Direction, SnakeHead},
+    AppState, InGameState,
+    input_intent_mod::{Intent, PLAYER_ONE},
+    snake_game_mod::Direction,
+    state_in_game_mod::{ButtonEnum, SnakeHead},
 };
 
-pub fn handle_movement_input(keys: Res<ButtonInput<KeyCode>>, mut snake_head: Single<&mut SnakeHead>, mut next_state: ResMut<NextState<AppState>>) {
-    if (keys.pressed(KeyCode::ArrowUp) || keys.pressed(KeyCode::KeyW)) && snake_head.direction != Direction::Down {
-        snake_head.new_direction = Direction::Up;
-    } else if (keys.pressed(KeyCode::ArrowRight) || keys.pressed(KeyCode::KeyD)) && snake_head.direction != Direction::Left {
-        snake_head.new_direction = Direction::Right;
-    } else if (keys.pressed(KeyCode::ArrowDown) || keys.pressed(KeyCode::KeyS)) && snake_head.direction != Direction::Up {
-        snake_head.new_direction = Direction::Down;
-    } else if (keys.pressed(KeyCode::ArrowLeft) || keys.pressed(KeyCode::KeyA)) && snake_head.direction != Direction::Right {
-        snake_head.new_direction = Direction::Left;
-    } else if keys.pressed(KeyCode::KeyX) {
-        next_state.set(AppState::MainMenu);
+/// All the input sources send intents. Every turn is queued, the queue validates the turns.
+/// The same system pauses and resumes, so one key press is never read twice.
+/// With one snake all the players steer it, with two snakes each player steers one snake.
+pub fn handle_movement_input(
+    mut intents: EventReader<Intent>,
+    mut snake_head_query: Query<&mut SnakeHead>,
+    in_game_state: Res<State<InGameState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_in_game_state: ResMut<NextState<InGameState>>,
+) {
+    let snake_count = snake_head_query.iter().count();
+    let is_paused = *in_game_state.get() == InGameState::Paused;
+    for intent in intents.read() {
+        match intent {
+            Intent::Turn(player, direction) => {
+                if is_paused {
+                    continue;
+                }
+                let snake_index = (*player).min(snake_count.saturating_sub(1));
+                for mut snake_head in snake_head_query.iter_mut().filter(|snake_head| snake_head.snake_index == snake_index) {
+                    let snake_head = &mut *snake_head;
+                    snake_head.direction_queue.push(direction.clone(), &snake_head.direction);
+                }
+            }
+            Intent::Quit => next_state.set(AppState::MainMenu),
+            Intent::Pause if is_paused => next_in_game_state.set(InGameState::Running),
+            Intent::Pause => next_in_game_state.set(InGameState::Paused),
+            Intent::Start if is_paused => next_in_game_state.set(InGameState::Running),
+            Intent::Start | Intent::Replay | Intent::Menu(_) => {}
+        }
     }
 }
 
+/// the on-screen buttons send the same intents as the keyboard
 #[allow(clippy::type_complexity)]
-pub fn button_interaction_system(
-    mut snake_head: Single<&mut SnakeHead>,
-    interaction_query: Query<(&ButtonEnum, &Interaction), (Changed<Interaction>, With<Button>)>,
-    mut next_state: ResMut<NextState<AppState>>,
-) {
+pub fn button_interaction_system(interaction_query: Query<(&ButtonEnum, &Interaction), (Changed<Interaction>, With<Button>)>, mut intents: EventWriter<Intent>) {
     for interaction in interaction_query {
         if *interaction.1 == Interaction::Pressed {
-            if *interaction.0 == ButtonEnum::KeyUp && snake_head.direction != Direction::Up {
-                snake_head.new_direction = Direction::Up;
-            } else if *interaction.0 == ButtonEnum::KeyRight && snake_head.direction != Direction::Right {
-                snake_head.new_direction = Direction::Right;
-            } else if *interaction.0 == ButtonEnum::KeyDown && snake_head.direction != Direction::Down {
-                snake_head.new_direction = Direction::Down;
-            } else if *interaction.0 == ButtonEnum::KeyLeft && snake_head.direction != Direction::Left {
-                snake_head.new_direction = Direction::Left;
-            } else if *interaction.0 == ButtonEnum::KeyX {
-                next_state.set(AppState::MainMenu);
-            }
+            intents.write(button_to_intent(interaction.0));
         }
     }
 }
+
+fn button_to_intent(button: &ButtonEnum) -> Intent {
+    match button {
+        ButtonEnum::KeyUp => Intent::Turn(PLAYER_ONE, Direction::Up),
+        ButtonEnum::KeyRight => Intent::Turn(PLAYER_ONE, Direction::Right),
+        ButtonEnum::KeyDown => Intent::Turn(PLAYER_ONE, Direction::Down),
+        ButtonEnum::KeyLeft => Intent::Turn(PLAYER_ONE, Direction::Left),
+    }
+}
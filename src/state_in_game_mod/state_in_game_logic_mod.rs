@@ -1,134 +1,283 @@
-use bevy::{
-    color::palettes::css::{ALICE_BLUE, AZURE, BISQUE, CHOCOLATE, CORAL, CRIMSON, GREEN, RED, WHITE},
-    prelude::*,
-};
+use bevy::prelude::*;
 use bevy_kira_audio::AudioControl;
 
 use crate::{
-    AppState, BOARD_HEIGHT, BOARD_WIDTH, GameBoardCanvas,
-    state_in_game_mod::{Bird, DebugText, Direction, OTHER_Z_LAYER, SnakeHead, SnakeSegment, SnakeSegmentIndex},
+    AppState, GameBoardCanvas,
+    autopilot_mod::Autopilot,
+    game_assets_mod::{GameAssets, SnakePart},
+    game_config_mod::GameConfig,
+    game_rng_mod::GameRng,
+    input_intent_mod::PLAYER_ONE,
+    online_mod::OnlineSession,
+    replay_mod::{GameRecording, ReplayPlayback, Turn},
+    snake_game_mod::{Food, FoodKind, GameEvent, PowerUp, SLOW_MOTION_FACTOR, Segment, SnakeGame},
+    state_in_game_mod::{Bird, DebugText, GameClock, GameOver, GameOverCause, OTHER_Z_LAYER, SnakeGameRes, SnakeHead, SnakeSegment, SnakeSegmentIndex, StepEvents},
+    theme_mod::Theme,
 };
 
-const BIRD_COLORS: [Srgba; 9] = [GREEN, RED, WHITE, AZURE, BISQUE, ALICE_BLUE, CHOCOLATE, CORAL, CRIMSON];
+/// the tint of the kind, else the random color of the bird from the theme, a wrong hex in the config is also the random color
+pub fn food_color(food: &Food, food_kind: &FoodKind, theme: &Theme) -> Color {
+    food_kind.tint.as_ref().and_then(|tint| Srgba::hex(tint).ok()).map_or(theme.bird_color(food.color), Color::from)
+}
 
-// fixed time every 0.5 seconds
-pub fn move_snake_head(mut snake_query: Query<&mut SnakeHead>) {
-    if let Ok(mut snake_head) = snake_query.single_mut() {
-        snake_head.last_direction = snake_head.direction.clone();
-        snake_head.direction = snake_head.new_direction.clone();
+/// in FixedUpdate the delta is the length of this step
+pub fn tick_game_clock(time: Res<Time>, mut game_clock: ResMut<GameClock>) {
+    game_clock.0 += time.delta();
+}
 
-        snake_head.last_position = snake_head.position.clone();
+/// AI assist: the computer turns only when the player did not, the player can always take over.
+/// The menu has one switch for player one, player two in the local game always plays alone.
+pub fn steer_with_autopilot(mut autopilot: ResMut<Autopilot>, game: Res<SnakeGameRes>, snake_head_query: Query<&mut SnakeHead>) {
+    for mut snake_head in snake_head_query {
+        if snake_head.snake_index != PLAYER_ONE || !snake_head.direction_queue.is_empty() {
+            continue;
+        }
+        if let Some(direction) = autopilot.0.next_direction(&game.0, snake_head.snake_index) {
+            let snake_head = &mut *snake_head;
+            snake_head.direction_queue.push(direction, &snake_head.direction);
+        }
+    }
+}
 
-        match &snake_head.direction {
-            Direction::Up => snake_head.position.y -= 1,
-            Direction::Down => snake_head.position.y += 1,
-            Direction::Left => snake_head.position.x -= 1,
-            Direction::Right => snake_head.position.x += 1,
+// fixed time every GameConfig.step_duration seconds
+// all the game logic is in SnakeGame::step(), the next systems only react to the events of this step
+// the turns of the players are recorded, in the replay the turns come from the recording
+pub fn move_snake_head(
+    mut game: ResMut<SnakeGameRes>,
+    mut game_rng: ResMut<GameRng>,
+    mut step_events: ResMut<StepEvents>,
+    mut snake_head_query: Query<&mut SnakeHead>,
+    mut game_recording: ResMut<GameRecording>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    let tick = game.0.moves;
+    let snake_count = game.0.snakes.len();
+    let inputs = match playback {
+        Some(mut playback) => playback.inputs_for_tick(tick, snake_count),
+        None => {
+            let mut inputs = vec![None; snake_count];
+            for mut snake_head in snake_head_query.iter_mut() {
+                let Some(direction) = snake_head.direction_queue.pop() else {
+                    continue;
+                };
+                game_recording.0.turns.push(Turn {
+                    tick,
+                    snake_index: snake_head.snake_index,
+                    direction: direction.clone(),
+                });
+                inputs[snake_head.snake_index] = Some(direction);
+            }
+            inputs
         }
-        snake_head.moves += 1;
+    };
+    step_events.0 = game.0.step(&inputs, game_rng.as_mut());
+    sync_snake_heads(&game.0, snake_head_query);
+}
+
+/// Instead of move_snake_head in the online game: the snapshots from the server replace the game.
+/// The events of all the snapshots since the last step go to the next systems, so no bird is missed.
+/// A lost connection ends the game.
+pub fn apply_online_snapshot(
+    mut commands: Commands,
+    mut online_session: ResMut<OnlineSession>,
+    mut game: ResMut<SnakeGameRes>,
+    mut step_events: ResMut<StepEvents>,
+    snake_head_query: Query<&mut SnakeHead>,
+    game_clock: Res<GameClock>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    step_events.0.clear();
+    for (snapshot, events) in online_session.take_snapshots() {
+        game.0 = snapshot;
+        step_events.0.extend(events);
+    }
+    sync_snake_heads(&game.0, snake_head_query);
+    if online_session.is_connection_lost && !game.0.is_over() {
+        warn!("online connection lost");
+        commands.insert_resource(game_over(GameOverCause::ConnectionLost, &game.0, &game_clock, false, true));
+        next_state.set(AppState::Dead);
+    }
+}
+
+/// the entities of the heads get the position and direction from SnakeGame
+/// the first segment is the cell where the head was, with the direction in and out of it, a head that did not move stands still
+fn sync_snake_heads(game: &SnakeGame, snake_head_query: Query<&mut SnakeHead>) {
+    for mut snake_head in snake_head_query {
+        let snake = &game.snakes[snake_head.snake_index];
+        snake_head.from = match snake.segments.front() {
+            Some(neck) if snake.head != snake_head.position => neck.clone(),
+            _ => Segment {
+                position: snake.head.clone(),
+                direction: snake.direction.clone(),
+                last_direction: snake.direction.clone(),
+            },
+        };
+        snake_head.position = snake.head.clone();
+        snake_head.direction = snake.direction.clone();
+    }
+}
+
+/// the difficulty curve: the fixed timestep gets shorter when the points grow
+/// the replay can be faster or slower than the recorded game, the slow motion power-up makes the steps longer
+pub fn speed_up_snake(game: Res<SnakeGameRes>, game_config: Res<GameConfig>, playback: Option<Res<ReplayPlayback>>, mut time: ResMut<Time<Fixed>>) {
+    let playback_speed = playback.map_or(1.0, |playback| playback.speed);
+    let slow_motion = if game.0.is_slow_motion() { SLOW_MOTION_FACTOR } else { 1.0 };
+    let step_duration = std::time::Duration::from_secs_f64(game_config.step_duration_for_points(game.0.points()) * slow_motion / playback_speed);
+    if time.timestep() != step_duration {
+        time.set_timestep(step_duration);
     }
 }
 
-// this is executed after snake_head_move
+// this is executed after move_snake_head
+// the cause of the end of the game goes into the GameOver resource for the Dead state
+// with two snakes both can die in the same step, the first cause is shown
+// a reached goal goes to the transition screen of the campaign, the replay shows it on the dead screen
+#[allow(clippy::too_many_arguments)]
 pub fn check_dead(
-    mut snake_query: Query<&mut SnakeHead>,
-    segment_query: Query<&mut SnakeSegment>,
+    mut commands: Commands,
+    step_events: Res<StepEvents>,
+    game: Res<SnakeGameRes>,
+    game_clock: Res<GameClock>,
+    playback: Option<Res<ReplayPlayback>>,
+    online_session: Option<Res<OnlineSession>>,
     mut next_state: ResMut<NextState<AppState>>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
     audio: Res<bevy_kira_audio::Audio>,
 ) {
-    if let Ok(snake_head) = snake_query.single_mut() {
-        if snake_head.position.x < 0 || snake_head.position.y < 0 || snake_head.position.x >= BOARD_WIDTH || snake_head.position.y >= BOARD_HEIGHT {
-            next_state.set(AppState::Dead);
-            audio.play(asset_server.load("game_over.mp3"));
-        } else {
-            for segment in segment_query {
-                if segment.position == snake_head.position {
-                    next_state.set(AppState::Dead);
-                    audio.play(asset_server.load("game_over.mp3"));
-                    break;
-                }
-            }
+    let Some(cause) = step_events.0.iter().find_map(|event| match event {
+        GameEvent::Died { snake_index, cause } => {
+            debug!("snake {snake_index} is dead: {cause:?}");
+            audio.play(game_assets.game_over.clone());
+            Some(GameOverCause::Died(cause.clone()))
         }
-    }
+        GameEvent::BoardCleared => {
+            info!("board cleared");
+            audio.play(game_assets.bird_chirp.clone());
+            Some(GameOverCause::BoardCleared)
+        }
+        GameEvent::GoalReached { snake_index } => {
+            info!("goal of the level {} reached", game.0.level.name);
+            audio.play(game_assets.bird_chirp.clone());
+            Some(GameOverCause::GoalReached(*snake_index))
+        }
+        _ => None,
+    }) else {
+        return;
+    };
+    let is_level_complete = matches!(cause, GameOverCause::GoalReached(_)) && playback.is_none() && online_session.is_none();
+    commands.insert_resource(game_over(cause, &game.0, &game_clock, playback.is_some(), online_session.is_some()));
+    next_state.set(if is_level_complete { AppState::LevelComplete } else { AppState::Dead });
 }
 
-pub fn eat_bird(mut snake_query: Query<&mut SnakeHead>, bird_query: Query<&mut Bird>, mut debug_text_query: Query<&mut DebugText>, asset_server: Res<AssetServer>, audio: Res<bevy_kira_audio::Audio>) {
-    if let Ok(mut snake_head) = snake_query.single_mut() {
-        for mut bird in bird_query {
-            if snake_head.position == bird.position {
-                audio.play(asset_server.load("bird_chirp.mp3"));
+/// the final numbers of the game for the Dead state
+fn game_over(cause: GameOverCause, game: &SnakeGame, game_clock: &GameClock, is_replay: bool, is_online: bool) -> GameOver {
+    // the first snake at the goal wins, else the last snake alive
+    let winner = match cause {
+        GameOverCause::GoalReached(snake_index) => Some(snake_index),
+        _ => game.winner(),
+    };
+    GameOver {
+        cause,
+        points: game.points(),
+        moves: game.moves,
+        length: game.snakes.iter().map(|snake| snake.length()).max().unwrap_or(0),
+        snake_points: game.snakes.iter().map(|snake| snake.points).collect(),
+        winner,
+        survived: game_clock.0,
+        is_replay,
+        is_online,
+    }
+}
 
-                snake_head.just_eating = true;
-                snake_head.points += 1;
-                // food: point, longer body
-                // new random position
-                bird.position.x = ops::floor(js_sys::Math::random() as f32 * BOARD_WIDTH as f32) as i32;
-                bird.position.y = ops::floor(js_sys::Math::random() as f32 * BOARD_HEIGHT as f32) as i32;
-                let random_color = ops::floor(js_sys::Math::random() as f32 * BIRD_COLORS.len() as f32) as usize;
-                bird.color = BIRD_COLORS[random_color].into();
+pub fn eat_bird(
+    step_events: Res<StepEvents>,
+    game: Res<SnakeGameRes>,
+    mut bird_query: Query<&mut Bird>,
+    mut debug_text_query: Query<&mut DebugText>,
+    game_assets: Res<GameAssets>,
+    theme: Res<Theme>,
+    audio: Res<bevy_kira_audio::Audio>,
+) {
+    for event in &step_events.0 {
+        let food_index = match event {
+            // every kind has its own sound, the pitch makes the same sound different
+            GameEvent::Ate { food_index, kind, .. } => {
+                let food_kind = game.0.food_kind(*kind);
+                audio.play(game_assets.food_sound(food_kind)).with_playback_rate(food_kind.sound_pitch);
+                food_index
+            }
+            GameEvent::FoodExpired { food_index } | GameEvent::FoodSpawned { food_index } => food_index,
+            GameEvent::PoweredUp { power_up, .. } => {
+                // the hiss is deep for the slow motion and high for the ghost
+                let playback_rate = match power_up {
+                    PowerUp::SlowMotion(_) => 0.5,
+                    PowerUp::Ghost => 1.5,
+                };
+                audio.play(game_assets.snake_hiss.clone()).with_playback_rate(playback_rate);
+                continue;
+            }
+            _ => continue,
+        };
 
-                if let Ok(mut debug_text) = debug_text_query.single_mut() {
-                    debug_text.bird_position = format!("{:?}", &bird.position);
-                }
+        // the eaten or expired bird is already a new bird on a new cell, or waits for a free cell in the zones
+        let food = &game.0.food[*food_index];
+        for mut bird in bird_query.iter_mut() {
+            if bird.index == *food_index {
+                bird.position = food.position.clone();
+                bird.color = food_color(food, game.0.food_kind(food.kind), &theme);
+                bird.kind = food.kind;
+                bird.is_waiting = food.is_waiting;
             }
         }
+        if let Ok(mut debug_text) = debug_text_query.single_mut() {
+            debug_text.bird_position = format!("{:?}", &food.position);
+        }
     }
 }
 
 /// first segment is after the snake head
+/// copy the segments from SnakeGame into the entities, spawn the new ones when a snake grows
 pub fn move_segments(
     mut commands: Commands,
-    mut snake_query: Query<&mut SnakeHead>,
-    mut segment_query: Query<(&mut SnakeSegment, &mut SnakeSegmentIndex)>,
-    asset_server: Res<AssetServer>,
+    game: Res<SnakeGameRes>,
+    mut segment_query: Query<(Entity, &mut SnakeSegment, &SnakeSegmentIndex)>,
+    game_assets: Res<GameAssets>,
+    theme: Res<Theme>,
     game_board_canvas: Res<GameBoardCanvas>,
 ) {
-    if let Ok(mut snake_head) = snake_query.single_mut() {
-        // Sort according to `usize index`.
-        let mut sorted_snake_segments: Vec<_> = segment_query
-            .iter_mut()
-            .sort_by::<(&SnakeSegment, &SnakeSegmentIndex)>(|value_1, value_2| value_1.1.index.cmp(&value_2.1.index))
-            .collect();
-
-        for snake_segment in sorted_snake_segments.iter_mut() {
-            snake_segment.1.index += 1;
+    let snakes = &game.0.snakes;
+    let mut existing = vec![0; snakes.len()];
+    for (entity, mut snake_segment, snake_segment_index) in segment_query.iter_mut() {
+        let segments = &snakes[snake_segment_index.snake_index].segments;
+        match segments.get(snake_segment_index.index) {
+            Some(segment) => {
+                let from = snake_segment.cell();
+                *snake_segment = SnakeSegment {
+                    from,
+                    ..SnakeSegment::from_segment(segment, snake_segment_index.index == segments.len() - 1)
+                };
+                existing[snake_segment_index.snake_index] += 1;
+            }
+            None => commands.entity(entity).despawn(),
         }
-
-        // if eating, spawn the new index zero, all rest of segments remain equal
-        if snake_head.just_eating {
-            snake_head.just_eating = false;
-
-            commands.spawn((
-                Sprite::from_image(asset_server.load("segment_horizontal.png")),
-                Transform::from_xyz(
-                    snake_head.last_position.to_bevy_x(&game_board_canvas),
-                    snake_head.last_position.to_bevy_y(&game_board_canvas),
-                    OTHER_Z_LAYER,
-                ),
-                SnakeSegment {
-                    position: snake_head.last_position.clone(),
-                    direction: snake_head.direction.clone(),
-                    last_direction: snake_head.last_direction.clone(),
-                    is_tail: false,
-                },
-                SnakeSegmentIndex { index: 0 },
-            ));
-        } else {
-            // the last segment becomes the first (zero) to avoid new spawn()
-            let new_first_segment = sorted_snake_segments.last_mut().unwrap();
-            // new values for the new first segment
-            new_first_segment.1.index = 0;
-            new_first_segment.0.position = snake_head.last_position.clone();
-            new_first_segment.0.direction = snake_head.direction.clone();
-            new_first_segment.0.last_direction = snake_head.last_direction.clone();
-            new_first_segment.0.is_tail = false;
-
-            // the new last segment becomes the tail
-            // The segments are always at least 2 elements.
-            let segment_len = sorted_snake_segments.len();
-            let new_last_segment = sorted_snake_segments.get_mut(segment_len - 2).unwrap();
-            new_last_segment.0.is_tail = true;
+    }
+    for (snake_index, snake) in snakes.iter().enumerate() {
+        for (index, segment) in snake.segments.iter().enumerate().skip(existing[snake_index]) {
+            spawn_segment(&mut commands, &game_assets, &theme, &game_board_canvas, segment, snake_index, index, index == snake.segments.len() - 1);
         }
     }
 }
+
+/// spawn the segment entity, the render_segment system will choose the atlas index and rotation
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_segment(commands: &mut Commands, game_assets: &GameAssets, theme: &Theme, game_board_canvas: &GameBoardCanvas, segment: &Segment, snake_index: usize, index: usize, is_tail: bool) {
+    commands.spawn((
+        Sprite {
+            color: theme.snake_tint(snake_index),
+            ..game_assets.snake_sprite(if is_tail { SnakePart::Tail } else { SnakePart::Body })
+        },
+        Transform::from_xyz(segment.position.to_bevy_x(game_board_canvas), segment.position.to_bevy_y(game_board_canvas), OTHER_Z_LAYER),
+        SnakeSegment::from_segment(segment, is_tail),
+        SnakeSegmentIndex { snake_index, index },
+    ));
+}
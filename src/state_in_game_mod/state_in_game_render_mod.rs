@@ -1,13 +1,17 @@
-use core::f32;
-use std::f32::consts::PI;
+use std::f32::consts::FRAC_PI_2;
 
 use bevy::prelude::*;
 
 use crate::{
     GameBoardCanvas,
-    state_in_game_mod::{Bird, DebugText, Direction, PointsText, SnakeHead, SnakeSegment},
+    game_assets_mod::{GameAssets, SnakePart},
+    snake_game_mod::{Direction, Segment},
+    state_in_game_mod::{Bird, DebugText, PointsText, SnakeGameRes, SnakeHead, SnakeSegment},
 };
 
+/// the timed bird blinks in its last steps
+const BLINK_STEPS: i32 = 5;
+
 /*
 pub fn draw_axis(mut gizmos: Gizmos) {
     // gizmos.axes_2d(transform, 300);
@@ -16,125 +20,174 @@ pub fn draw_axis(mut gizmos: Gizmos) {
 }
 */
 
-pub fn render_snake_head(mut snake_head_query: Query<(&mut SnakeHead, &mut Transform), Changed<SnakeHead>>, game_board_canvas: Res<GameBoardCanvas>) {
-    if let Ok((snake_head, mut transform)) = snake_head_query.single_mut() {
-        transform.translation.x = snake_head.position.to_bevy_x(&game_board_canvas);
-        transform.translation.y = snake_head.position.to_bevy_y(&game_board_canvas);
-
-        // TODO: hot to flip using look_at???
-        // rotate and/or flip head
-        match snake_head.direction {
-            Direction::Up => {
-                transform.look_at(
-                    Vec3 {
-                        x: snake_head.position.to_bevy_x(&game_board_canvas),
-                        y: snake_head.position.to_bevy_y(&game_board_canvas),
-                        z: f32::INFINITY,
-                    },
-                    Vec3::X,
-                );
-            }
-            Direction::Right => {
-                transform.look_at(
-                    Vec3 {
-                        x: snake_head.position.to_bevy_x(&game_board_canvas),
-                        y: snake_head.position.to_bevy_y(&game_board_canvas),
-                        z: f32::INFINITY,
-                    },
-                    Vec3 { x: 0.0, y: -1.0, z: 0.0 },
-                );
-                transform.rotate_x(PI);
-            }
-            Direction::Down => {
-                transform.look_at(
-                    Vec3 {
-                        x: snake_head.position.to_bevy_x(&game_board_canvas),
-                        y: snake_head.position.to_bevy_y(&game_board_canvas),
-                        z: f32::INFINITY,
-                    },
-                    -Vec3::X,
-                );
-            }
-            Direction::Left => {
-                transform.look_at(
-                    Vec3 {
-                        x: snake_head.position.to_bevy_x(&game_board_canvas),
-                        y: snake_head.position.to_bevy_y(&game_board_canvas),
-                        z: f32::INFINITY,
-                    },
-                    Vec3::Y,
-                );
-            }
+/// The head moves smoothly from the last cell to the current cell, the next cell is the queued turn or straight on.
+pub fn render_snake_head(snake_head_query: Query<(&SnakeHead, &mut Transform, &mut Sprite)>, time: Res<Time<Fixed>>, game_board_canvas: Res<GameBoardCanvas>) {
+    for (snake_head, mut transform, mut sprite) in snake_head_query {
+        let current = Segment {
+            position: snake_head.position.clone(),
+            direction: snake_head.direction_queue.peek().unwrap_or(&snake_head.direction).clone(),
+            last_direction: snake_head.direction.clone(),
         };
+        let (translation, part_sprite) = match moving_on_path(&snake_head.from, &current, time.overstep_fraction(), &game_board_canvas) {
+            Some((translation, tangent)) => (translation, facing(SnakePart::Head, tangent)),
+            None => (cell_center(&current, &game_board_canvas), facing(SnakePart::Head, direction_vector(&snake_head.direction))),
+        };
+        place_part(&mut transform, &mut sprite, translation, &part_sprite);
     }
 }
-pub fn render_bird(queried_entities: Query<(&mut Bird, &mut Transform, &mut Sprite), Changed<Bird>>, game_board_canvas: Res<GameBoardCanvas>) {
-    for (bird, mut transform, mut sprite) in queried_entities {
+
+/// every kind of food has its own image, tint and scale
+pub fn render_bird(
+    queried_entities: Query<(&mut Bird, &mut Transform, &mut Sprite, &mut Visibility), Changed<Bird>>,
+    game: Res<SnakeGameRes>,
+    game_assets: Res<GameAssets>,
+    game_board_canvas: Res<GameBoardCanvas>,
+) {
+    for (bird, mut transform, mut sprite, mut visibility) in queried_entities {
+        let food_kind = game.0.food_kind(bird.kind);
         transform.translation.x = bird.position.to_bevy_x(&game_board_canvas);
         transform.translation.y = bird.position.to_bevy_y(&game_board_canvas);
+        transform.scale = Vec3::splat(food_kind.scale);
+        sprite.image = game_assets.food_image(food_kind);
         sprite.color = bird.color;
+        *visibility = if bird.is_waiting { Visibility::Hidden } else { Visibility::Inherited };
     }
 }
 
-#[allow(clippy::type_complexity)]
-pub fn render_segment(
-    mut segment_query: Query<(&mut SnakeSegment, &mut Transform, &mut Sprite), Or<(Changed<SnakeSegment>, Added<SnakeSegment>)>>,
-    asset_server: Res<AssetServer>,
-    game_board_canvas: Res<GameBoardCanvas>,
-) {
-    for (segment, mut transform, mut sprite) in segment_query.iter_mut() {
-        transform.translation.x = segment.position.to_bevy_x(&game_board_canvas);
-        transform.translation.y = segment.position.to_bevy_y(&game_board_canvas);
-
-        // the last segment is the tail
-        if segment.is_tail {
-            sprite.image = asset_server.load("segment_tail.png");
-        } else if segment.direction != segment.last_direction {
-            sprite.image = asset_server.load("segment_corner.png");
-        } else {
-            sprite.image = asset_server.load("segment_horizontal.png");
+/// a timed bird blinks in its last steps before it flies away
+pub fn blink_expiring_birds(game: Res<SnakeGameRes>, time: Res<Time>, bird_query: Query<(&Bird, &mut Sprite)>) {
+    for (bird, mut sprite) in bird_query {
+        let Some(food) = game.0.food.get(bird.index) else {
+            continue;
+        };
+        let is_expiring = food.expires_at.is_some_and(|expires_at| expires_at - game.0.moves <= BLINK_STEPS);
+        let alpha = if is_expiring && (time.elapsed_secs() * 8.0).sin() < 0.0 { 0.3 } else { bird.color.alpha() };
+        sprite.color.set_alpha(alpha);
+    }
+}
+
+/// the ghost snake is half transparent until it passes through itself
+pub fn render_ghost_snake(game: Res<SnakeGameRes>, snake_head_query: Query<(&SnakeHead, &mut Sprite)>) {
+    for (snake_head, mut sprite) in snake_head_query {
+        let is_ghost = game.0.snakes.get(snake_head.snake_index).is_some_and(|snake| snake.is_ghost);
+        let alpha = if is_ghost { 0.5 } else { 1.0 };
+        if sprite.color.alpha() != alpha {
+            sprite.color.set_alpha(alpha);
         }
-        rotate_segment(&segment, transform, &game_board_canvas);
     }
 }
 
-// corner can be clockwise and counterclockwise
-fn rotate_segment(segment: &Mut<'_, SnakeSegment>, mut transform: Mut<'_, Transform>, game_board_canvas: &Res<GameBoardCanvas>) {
-    let forward = Vec3 {
-        x: segment.position.to_bevy_x(game_board_canvas),
-        y: segment.position.to_bevy_y(game_board_canvas),
-        z: f32::INFINITY,
-    };
+/// Every segment follows the head on the same path, so the body bends in an arc around the corners.
+/// A segment that stands still shows the tile of its cell, also the corner tile.
+pub fn render_segment(segment_query: Query<(&SnakeSegment, &mut Transform, &mut Sprite)>, time: Res<Time<Fixed>>, game_board_canvas: Res<GameBoardCanvas>) {
+    for (segment, mut transform, mut sprite) in segment_query {
+        let current = segment.cell();
+        let (translation, part_sprite) = match moving_on_path(&segment.from, &current, time.overstep_fraction(), &game_board_canvas) {
+            // the last segment is the tail
+            Some((translation, tangent)) => (translation, facing(if segment.is_tail { SnakePart::Tail } else { SnakePart::Body }, tangent)),
+            None => (cell_center(&current, &game_board_canvas), part_sprite(&current.direction, &current.last_direction, segment.is_tail)),
+        };
+        place_part(&mut transform, &mut sprite, translation, &part_sprite);
+    }
+}
+
+/// The tile of the sprite sheet, the rotation around z and the flip of a part of the snake.
+/// The tiles need only flip_y, because a flip_x is the same as flip_y and a half turn.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PartSprite {
+    pub snake_part: SnakePart,
+    pub rotation: f32,
+    pub flip_y: bool,
+}
 
-    match segment.direction {
-        Direction::Up => {
-            transform.look_at(forward, Vec3::X);
-            // if anti-clockwise
-            if segment.last_direction == Direction::Right {
-                transform.rotate_y(PI);
-            }
-        }
-        Direction::Right => {
-            transform.look_at(forward, -Vec3::Y);
-            // if anti-clockwise
-            if segment.last_direction == Direction::Down {
-                transform.rotate_x(PI);
-            }
-        }
-        Direction::Down => {
-            transform.look_at(forward, -Vec3::X);
-            // if anti-clockwise
-            if segment.last_direction == Direction::Left {
-                transform.rotate_y(PI);
-            }
-        }
-        Direction::Left => {
-            transform.look_at(forward, Vec3::Y);
-            // if anti-clockwise
-            if segment.last_direction == Direction::Up {
-                transform.rotate_x(PI);
-            }
-        }
+/// The tile of a segment that stands still in its cell, direction goes out to the head and last_direction comes in from the tail.
+/// The head, body and tail tiles look to the right, the corner tile comes in going down and goes out to the right.
+/// All the 16 pairs of directions are listed, so the compiler checks that none is missing.
+pub fn part_sprite(direction: &Direction, last_direction: &Direction, is_tail: bool) -> PartSprite {
+    use Direction::{Down, Left, Right, Up};
+    match (direction, last_direction) {
+        _ if is_tail => facing(SnakePart::Tail, direction_vector(direction)),
+        // straight on, the reverse is not possible in the game and looks straight
+        (Up, Up | Down) | (Down, Down | Up) | (Left, Left | Right) | (Right, Right | Left) => facing(SnakePart::Body, direction_vector(direction)),
+        // the turns like the corner tile are only rotated
+        (Right, Down) | (Down, Left) | (Left, Up) | (Up, Right) => PartSprite {
+            snake_part: SnakePart::Corner,
+            rotation: direction_vector(direction).to_angle(),
+            flip_y: false,
+        },
+        // the turns the other way round are also flipped
+        (Right, Up) | (Up, Left) | (Left, Down) | (Down, Right) => PartSprite {
+            snake_part: SnakePart::Corner,
+            rotation: direction_vector(direction).to_angle(),
+            flip_y: true,
+        },
+    }
+}
+
+/// A straight part looks in the direction of the movement, going to the left it is flipped, so it is never upside down.
+/// The small tolerance is for the rounding at the end of an arc, where the movement is up or down.
+fn facing(snake_part: SnakePart, movement: Vec2) -> PartSprite {
+    PartSprite {
+        snake_part,
+        rotation: movement.to_angle(),
+        flip_y: movement.x < -0.001,
+    }
+}
+
+/// the atlas index changes only if it is different, so the sprite is not changed every frame for nothing
+fn place_part(transform: &mut Transform, sprite: &mut Sprite, translation: Vec2, part_sprite: &PartSprite) {
+    transform.translation.x = translation.x;
+    transform.translation.y = translation.y;
+    transform.rotation = Quat::from_rotation_z(part_sprite.rotation);
+    if sprite.flip_y != part_sprite.flip_y {
+        sprite.flip_y = part_sprite.flip_y;
+    }
+    if let Some(texture_atlas) = sprite.texture_atlas.as_mut().filter(|texture_atlas| texture_atlas.index != part_sprite.snake_part as usize) {
+        texture_atlas.index = part_sprite.snake_part as usize;
+    }
+}
+
+fn cell_center(cell: &Segment, game_board_canvas: &GameBoardCanvas) -> Vec2 {
+    Vec2::new(cell.position.to_bevy_x(game_board_canvas), cell.position.to_bevy_y(game_board_canvas))
+}
+
+/// Between two fixed steps the piece goes from the middle of the last cell to the middle of the current cell.
+/// The fraction is the time since the last step, 0.0 to 1.0. Returns the translation and the direction of the movement.
+/// None is a piece that stands still, for example at the start of the game.
+/// With Walls::Wrap the piece leaves on one edge and comes in on the opposite edge.
+fn moving_on_path(last: &Segment, current: &Segment, fraction: f32, game_board_canvas: &GameBoardCanvas) -> Option<(Vec2, Vec2)> {
+    if last.position == current.position {
+        return None;
+    }
+    let (cell, progress) = if fraction < 0.5 { (last, 0.5 + fraction) } else { (current, fraction - 0.5) };
+    let (offset, tangent) = path_in_cell(&cell.last_direction, &cell.direction, progress);
+    Some((
+        cell_center(cell, game_board_canvas) + offset * Vec2::new(game_board_canvas.sprite_width, game_board_canvas.sprite_height),
+        tangent,
+    ))
+}
+
+/// The path through one cell in cell units from the center of the cell.
+/// Progress 0.0 is the edge where the snake comes in, 1.0 is the edge where it goes out.
+/// Straight on goes through the center, a turn is a quarter circle around the inner corner of the cell.
+fn path_in_cell(entry: &Direction, exit: &Direction, progress: f32) -> (Vec2, Vec2) {
+    let entry = direction_vector(entry);
+    let exit = direction_vector(exit);
+    if entry == exit || entry == -exit {
+        return (entry * (progress - 0.5), entry);
+    }
+    let inner_corner = (exit - entry) * 0.5;
+    let (sin, cos) = (progress * FRAC_PI_2).sin_cos();
+    (inner_corner + (entry * sin - exit * cos) * 0.5, entry * cos + exit * sin)
+}
+
+/// Bevy y goes up, the game y goes down
+fn direction_vector(direction: &Direction) -> Vec2 {
+    match direction {
+        Direction::Up => Vec2::Y,
+        Direction::Down => -Vec2::Y,
+        Direction::Left => -Vec2::X,
+        Direction::Right => Vec2::X,
     }
 }
 
@@ -144,10 +197,134 @@ pub fn render_debug_text(mut debug_text_query: Query<(&mut DebugText, &mut Text)
     }
 }
 
-pub fn render_points_text(snake_head_query: Query<&SnakeHead>, mut debug_text_query: Query<(&mut PointsText, &mut Text)>) {
-    if let Ok(snake_head) = snake_head_query.single() {
-        if let Ok((_points_text, mut text)) = debug_text_query.single_mut() {
-            *text = Text::new(format!("Moves:{} Points:{}", snake_head.moves, snake_head.points));
+/// the speed is in steps per second, with two players the points of both are shown
+pub fn render_points_text(
+    game: Res<SnakeGameRes>,
+    mut debug_text_query: Query<(&mut PointsText, &mut Text)>,
+    time: Res<Time<Fixed>>,
+    playback: Option<Res<crate::replay_mod::ReplayPlayback>>,
+    online_session: Option<Res<crate::online_mod::OnlineSession>>,
+) {
+    if let Ok((_points_text, mut text)) = debug_text_query.single_mut() {
+        let speed = 1.0 / time.timestep().as_secs_f32();
+        let points = match game.0.snakes.as_slice() {
+            [snake] => format!("Points:{}", snake.points),
+            snakes => snakes
+                .iter()
+                .enumerate()
+                .map(|(snake_index, snake)| format!("P{}:{}", snake_index + 1, snake.points))
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        let mode = match (playback, online_session) {
+            (Some(playback), _) => format!(" Replay x{}", playback.speed),
+            (None, Some(online_session)) => format!(" Online, you are P{}", online_session.player_index + 1),
+            (None, None) => String::new(),
+        };
+        let goal = match game.0.goal_progress() {
+            Some((value, target)) => format!(" Goal:{value}/{target}"),
+            None => String::new(),
+        };
+        let mut power_ups = String::new();
+        for (snake_index, _) in game.0.snakes.iter().enumerate().filter(|(_, snake)| snake.is_ghost) {
+            power_ups += &if game.0.snakes.len() > 1 { format!(" P{}:Ghost", snake_index + 1) } else { " Ghost".to_string() };
         }
+        if let Some(slow_motion_steps) = game.0.snakes.iter().map(|snake| snake.slow_motion_steps).max().filter(|steps| *steps > 0) {
+            power_ups += &format!(" Slow:{slow_motion_steps}");
+        }
+        *text = Text::new(format!("Moves:{} {points}{goal}{power_ups} Speed:{:.1}{mode}", game.0.moves, speed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn assert_part_sprite(direction: Direction, last_direction: Direction, is_tail: bool, snake_part: SnakePart, rotation: f32, flip_y: bool) {
+        let part = part_sprite(&direction, &last_direction, is_tail);
+        let context = format!("{direction:?} from {last_direction:?}, tail {is_tail}: {part:?}");
+        assert_eq!(part.snake_part, snake_part, "{context}");
+        // -PI and PI are the same rotation
+        assert!(Vec2::from_angle(part.rotation).distance(Vec2::from_angle(rotation)) < 1e-6, "{context}");
+        assert_eq!(part.flip_y, flip_y, "{context}");
+    }
+
+    fn assert_vec2(actual: Vec2, expected: Vec2) {
+        assert!((actual - expected).length() < 1e-6, "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn part_sprite_straight() {
+        use Direction::{Down, Left, Right, Up};
+        let cases = [
+            (Up, Up, PI / 2.0, false),
+            (Up, Down, PI / 2.0, false),
+            (Down, Down, -PI / 2.0, false),
+            (Down, Up, -PI / 2.0, false),
+            (Left, Left, PI, true),
+            (Left, Right, PI, true),
+            (Right, Right, 0.0, false),
+            (Right, Left, 0.0, false),
+        ];
+        for (direction, last_direction, rotation, flip_y) in cases {
+            assert_part_sprite(direction, last_direction, false, SnakePart::Body, rotation, flip_y);
+        }
+    }
+
+    #[test]
+    fn part_sprite_corners() {
+        use Direction::{Down, Left, Right, Up};
+        let cases = [
+            // like the tile, in going down and out to the right
+            (Right, Down, 0.0, false),
+            (Down, Left, -PI / 2.0, false),
+            (Left, Up, PI, false),
+            (Up, Right, PI / 2.0, false),
+            // the other way round
+            (Right, Up, 0.0, true),
+            (Up, Left, PI / 2.0, true),
+            (Left, Down, PI, true),
+            (Down, Right, -PI / 2.0, true),
+        ];
+        for (direction, last_direction, rotation, flip_y) in cases {
+            assert_part_sprite(direction, last_direction, false, SnakePart::Corner, rotation, flip_y);
+        }
+    }
+
+    #[test]
+    fn part_sprite_tail() {
+        use Direction::{Down, Left, Right, Up};
+        let cases = [(Up, PI / 2.0, false), (Down, -PI / 2.0, false), (Left, PI, true), (Right, 0.0, false)];
+        for (direction, rotation, flip_y) in cases {
+            // the tail looks only in its direction, also after a turn
+            for last_direction in [Up, Down, Left, Right] {
+                assert_part_sprite(direction.clone(), last_direction, true, SnakePart::Tail, rotation, flip_y);
+            }
+        }
+    }
+
+    #[test]
+    fn path_in_cell_straight() {
+        let (start, start_tangent) = path_in_cell(&Direction::Right, &Direction::Right, 0.0);
+        let (end, end_tangent) = path_in_cell(&Direction::Right, &Direction::Right, 1.0);
+        assert_vec2(start, Vec2::new(-0.5, 0.0));
+        assert_vec2(end, Vec2::new(0.5, 0.0));
+        assert_vec2(start_tangent, Vec2::X);
+        assert_vec2(end_tangent, Vec2::X);
+    }
+
+    #[test]
+    fn path_in_cell_turn() {
+        // in from the top edge going down, out on the right edge
+        let (start, start_tangent) = path_in_cell(&Direction::Down, &Direction::Right, 0.0);
+        let (end, end_tangent) = path_in_cell(&Direction::Down, &Direction::Right, 1.0);
+        assert_vec2(start, Vec2::new(0.0, 0.5));
+        assert_vec2(end, Vec2::new(0.5, 0.0));
+        assert_vec2(start_tangent, -Vec2::Y);
+        assert_vec2(end_tangent, Vec2::X);
+        // the arc stays half a cell from the inner corner
+        let (middle, _) = path_in_cell(&Direction::Down, &Direction::Right, 0.5);
+        assert!((middle.distance(Vec2::new(0.5, 0.5)) - 0.5).abs() < 1e-6);
     }
 }
@@ -0,0 +1,129 @@
+//! src/state_level_select_mod.rs
+//! The key L in the main menu opens the list of the levels.
+//! Up and Down choose the level, N plays it, X returns to the main menu without a change.
+//! The locked levels of the campaign cannot be played, see campaign_mod.
+
+use bevy::prelude::*;
+
+use crate::campaign_mod::CampaignProgress;
+use crate::game_assets_mod::GameAssets;
+use crate::game_config_mod::GameConfig;
+use crate::input_intent_mod::Intent;
+use crate::level_mod::{Levels, select_level};
+use crate::snake_game_mod::Direction;
+use crate::theme_mod::Theme;
+use crate::{AppState, GameBoardCanvas};
+
+/// the index of the chosen row, 0 is the open board and then the loaded levels
+#[derive(Resource)]
+struct LevelCursor(usize);
+
+#[derive(Component)]
+struct LevelListText;
+
+pub fn add_level_select_to_app(app: &mut App) {
+    app.add_systems(OnEnter(AppState::LevelSelect), on_enter_level_select);
+    app.add_systems(OnExit(AppState::LevelSelect), on_exit_level_select);
+    app.add_systems(
+        Update,
+        (
+            // the board has the size of the level before on_enter_in_game
+            (handle_level_select_input, crate::handle_browser_resize).chain().run_if(in_state(AppState::LevelSelect)),
+            render_level_list.run_if(in_state(AppState::LevelSelect)),
+        ),
+    );
+}
+
+/// the cursor starts on the selected level
+fn on_enter_level_select(mut commands: Commands, game_board_canvas: Res<GameBoardCanvas>, game_assets: Res<GameAssets>, theme: Res<Theme>, game_config: Res<GameConfig>, levels: Res<Levels>) {
+    let cursor = levels.loaded.iter().position(|level| level.name == game_config.level).map_or(0, |index| index + 1);
+    commands.insert_resource(LevelCursor(cursor));
+
+    commands.spawn((StateScoped(AppState::LevelSelect), Camera2d));
+    commands
+        .spawn((
+            StateScoped(AppState::LevelSelect),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+        ))
+        .with_children(|level_select| {
+            level_select.spawn((
+                Text::new("Select the level"),
+                game_assets.text_font(game_board_canvas.sprite_height * 2.),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.title_color()),
+            ));
+            level_select.spawn((
+                Text::new(""),
+                game_assets.text_font(game_board_canvas.sprite_height),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.text_color()),
+                LevelListText,
+            ));
+            level_select.spawn((
+                Text::new("Up and Down to choose, N to play, X to cancel"),
+                game_assets.text_font(game_board_canvas.sprite_height * 0.7),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.hint_color()),
+            ));
+        });
+}
+
+fn on_exit_level_select(mut commands: Commands) {
+    commands.remove_resource::<LevelCursor>();
+}
+
+fn handle_level_select_input(
+    mut intents: EventReader<Intent>,
+    levels: Res<Levels>,
+    campaign_progress: Res<CampaignProgress>,
+    mut cursor: ResMut<LevelCursor>,
+    mut game_config: ResMut<GameConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let row_count = levels.loaded.len() + 1;
+    for intent in intents.read() {
+        match intent {
+            Intent::Turn(_, Direction::Up) => cursor.0 = (cursor.0 + row_count - 1) % row_count,
+            Intent::Turn(_, Direction::Down) => cursor.0 = (cursor.0 + 1) % row_count,
+            Intent::Start => {
+                let level = cursor.0.checked_sub(1).and_then(|index| levels.loaded.get(index));
+                if level.is_some_and(|level| !campaign_progress.is_unlocked(&level.name)) {
+                    continue;
+                }
+                select_level(&mut game_config, level);
+                next_state.set(AppState::InGame);
+            }
+            Intent::Quit => next_state.set(AppState::MainMenu),
+            Intent::Turn(_, Direction::Left | Direction::Right) | Intent::Pause | Intent::Replay | Intent::Menu(_) => {}
+        }
+    }
+}
+
+/// the chosen row is marked with arrows, the levels loaded later are added to the list
+fn render_level_list(levels: Res<Levels>, cursor: Res<LevelCursor>, campaign_progress: Res<CampaignProgress>, mut level_list_text_query: Query<&mut Text, With<LevelListText>>) {
+    if !levels.is_changed() && !cursor.is_changed() {
+        return;
+    }
+    let Ok(mut text) = level_list_text_query.single_mut() else {
+        return;
+    };
+    let rows = std::iter::once("open board".to_string()).chain(levels.loaded.iter().map(|level| {
+        let progress = if campaign_progress.is_completed(&level.name) {
+            " done"
+        } else if campaign_progress.is_unlocked(&level.name) {
+            ""
+        } else {
+            " locked"
+        };
+        format!("{} {}x{}{progress}", level.name, level.board_width, level.board_height)
+    }));
+    let rows: Vec<String> = rows.enumerate().map(|(index, row)| if index == cursor.0 { format!("> {row} <") } else { row }).collect();
+    *text = Text::new(rows.join("\n"));
+}
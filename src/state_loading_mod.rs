@@ -0,0 +1,162 @@
+//! src/state_loading_mod.rs
+//! The first state: the images and sounds in GameAssets stream in over the network, then the main menu starts.
+//! The progress bar is over the same loading.jpg that index.html shows while the wasm is downloading.
+//! A failed asset is listed on the screen and in the log, N continues without it.
+
+use bevy::asset::LoadState;
+use bevy::color::palettes::css::BLACK;
+use bevy::prelude::*;
+
+use crate::game_assets_mod::{GameAssets, load_food_assets};
+use crate::game_config_mod::GameConfigHandle;
+use crate::input_intent_mod::Intent;
+use crate::theme_mod::{Theme, Themes};
+use crate::{AppState, GameBoardCanvas};
+
+#[derive(Component)]
+struct ProgressBar;
+
+#[derive(Component)]
+struct ProgressText;
+
+#[derive(Component)]
+struct FailedText;
+
+/// the failed assets are logged only once
+#[derive(Resource, Default)]
+struct FailedAssets(Vec<String>);
+
+pub fn add_loading_to_app(app: &mut App) {
+    app.add_systems(OnEnter(AppState::Loading), on_enter_loading);
+    app.add_systems(OnExit(AppState::Loading), on_exit_loading);
+    // the food kinds of the config are in GameAssets before the progress is checked
+    app.add_systems(Update, check_loading_progress.after(load_food_assets).run_if(in_state(AppState::Loading)));
+}
+
+/// OnEnter of the first state runs before Startup, so GameAssets does not exist yet and the text has the default font
+/// the colors are from the default Theme, the saved theme is not loaded yet
+fn on_enter_loading(mut commands: Commands, game_board_canvas: Res<GameBoardCanvas>, theme: Res<Theme>, asset_server: Res<AssetServer>) {
+    commands.init_resource::<FailedAssets>();
+    commands.spawn((StateScoped(AppState::Loading), Camera2d));
+    commands
+        .spawn((
+            StateScoped(AppState::Loading),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ImageNode::new(asset_server.load("loading.jpg")),
+        ))
+        .with_children(|loading| {
+            loading
+                .spawn((
+                    Node {
+                        width: Val::Percent(60.0),
+                        height: Val::Px(game_board_canvas.sprite_height),
+                        ..default()
+                    },
+                    BackgroundColor(BLACK.with_alpha(0.6).into()),
+                ))
+                .with_children(|progress_bar| {
+                    progress_bar.spawn((
+                        Node {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        BackgroundColor(theme.title_color()),
+                        ProgressBar,
+                    ));
+                });
+            loading.spawn((
+                Text::new("Loading"),
+                TextFont {
+                    font_size: game_board_canvas.sprite_height,
+                    ..default()
+                },
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.text_color()),
+                ProgressText,
+            ));
+            loading.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: game_board_canvas.sprite_height * 0.7,
+                    ..default()
+                },
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.hint_color()),
+                FailedText,
+            ));
+        });
+}
+
+fn on_exit_loading(mut commands: Commands) {
+    commands.remove_resource::<FailedAssets>();
+}
+
+/// Every handle is loaded or failed, also the themes, and the config is read because it lists the images and sounds of the food kinds.
+/// Without failed assets the main menu starts at once, else the player reads the list and presses N.
+#[allow(clippy::too_many_arguments)]
+fn check_loading_progress(
+    game_assets: Option<Res<GameAssets>>,
+    game_config_handle: Option<Res<GameConfigHandle>>,
+    themes: Res<Themes>,
+    asset_server: Res<AssetServer>,
+    mut failed_assets: ResMut<FailedAssets>,
+    mut intents: EventReader<Intent>,
+    mut progress_bar_query: Query<&mut Node, With<ProgressBar>>,
+    mut progress_text_query: Query<&mut Text, (With<ProgressText>, Without<FailedText>)>,
+    mut failed_text_query: Query<&mut Text, (With<FailedText>, Without<ProgressText>)>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let (Some(game_assets), Some(game_config_handle)) = (game_assets, game_config_handle) else {
+        return;
+    };
+    // the sprite sheet of the selected theme is in GameAssets when the theme is loaded
+    let mut handles = game_assets.handles();
+    handles.extend(themes.handles());
+    let mut loaded = 0;
+    let mut failed = 0;
+    for handle in &handles {
+        match asset_server.load_state(handle.id()) {
+            LoadState::Loaded => loaded += 1,
+            LoadState::Failed(load_error) => {
+                failed += 1;
+                let path = handle.path().map_or("unknown".to_string(), |path| path.to_string());
+                if !failed_assets.0.iter().any(|failed| failed.starts_with(&format!("{path}: "))) {
+                    error!("could not load the asset {path}: {load_error}");
+                    failed_assets.0.push(format!("{path}: {load_error}"));
+                }
+            }
+            LoadState::NotLoaded | LoadState::Loading => {}
+        }
+    }
+    let is_config_done = !matches!(asset_server.load_state(&game_config_handle.0), LoadState::NotLoaded | LoadState::Loading);
+    let is_done = is_config_done && loaded + failed == handles.len();
+
+    if let Ok(mut node) = progress_bar_query.single_mut() {
+        node.width = Val::Percent(100.0 * loaded as f32 / handles.len().max(1) as f32);
+    }
+    if let Ok(mut text) = progress_text_query.single_mut() {
+        *text = Text::new(match (is_done, failed_assets.0.is_empty()) {
+            (true, false) => format!("Loaded {loaded}/{}, press N to continue without the missing assets", handles.len()),
+            _ => format!("Loading {loaded}/{}", handles.len()),
+        });
+    }
+    if failed_assets.is_changed() {
+        for mut text in &mut failed_text_query {
+            *text = Text::new(failed_assets.0.join("\n"));
+        }
+    }
+
+    let is_confirmed = intents.read().any(|intent| *intent == Intent::Start);
+    if is_done && (failed_assets.0.is_empty() || is_confirmed) {
+        info!("assets loaded: {loaded}/{}", handles.len());
+        next_state.set(AppState::MainMenu);
+    }
+}
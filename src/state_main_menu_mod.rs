@@ -2,14 +2,26 @@
 
 use bevy::prelude::*;
 
-use crate::{AppState, GameBoardCanvas, Orientation, VERSION};
-use bevy::color::palettes::css::{GREEN, RED, WHITE, YELLOW};
+use crate::{
+    AppState, GameBoardCanvas, Orientation, VERSION,
+    game_assets_mod::GameAssets,
+    game_config_mod::GameConfig,
+    high_score_mod::{HighScores, spawn_high_score_table},
+    input_intent_mod::{Intent, MenuAction},
+    snake_game_mod::Walls,
+    theme_mod::{SelectedTheme, Theme},
+};
+use bevy::color::palettes::css::WHITE;
 
 #[derive(Component, PartialEq)]
 enum ButtonEnum {
     KeyN,
 }
 
+// the settings that can be toggled in the main menu
+#[derive(Component)]
+struct SettingsText {}
+
 pub fn add_main_menu_to_app(app: &mut App) {
     app.add_systems(OnEnter(AppState::MainMenu), on_enter_main_menu);
     app.add_systems(OnExit(AppState::MainMenu), on_exit_dead);
@@ -20,8 +32,11 @@ pub fn add_main_menu_to_app(app: &mut App) {
             handle_main_menu_ui_input.run_if(in_state(AppState::MainMenu)),
             crate::handle_browser_resize.run_if(in_state(AppState::MainMenu)),
             button_interaction_system.run_if(in_state(AppState::MainMenu)),
+            render_settings_text.run_if(in_state(AppState::MainMenu)),
         ),
     );
+    // the same state is not entered again, a new theme despawns and spawns the main menu, also a modified theme file
+    app.add_systems(Update, (on_exit_dead, on_enter_main_menu).chain().run_if(resource_changed::<Theme>.and(in_state(AppState::MainMenu))));
 }
 
 pub fn on_enter_main_menu(
@@ -30,7 +45,9 @@ pub fn on_enter_main_menu(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<crate::CustomMaterial>>,
     //mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
+    theme: Res<Theme>,
+    high_scores: Res<HighScores>,
 ) {
     commands.spawn(Camera2d);
 
@@ -42,6 +59,7 @@ pub fn on_enter_main_menu(
                 game_board_canvas.client_width as f32 / game_board_canvas.scale_factor,
                 game_board_canvas.client_height as f32 / game_board_canvas.scale_factor,
             ),
+            background: theme.background_palette(),
         })),
         Transform::default().with_scale(Vec3::new(game_board_canvas.client_width as f32, game_board_canvas.client_height as f32, 0.)),
     ));
@@ -85,12 +103,9 @@ pub fn on_enter_main_menu(
                     // Header
                     header_box.spawn((
                         Text::new("github.com/bestia-dev/snake_bevy_wasm"),
-                        TextFont {
-                            font_size: game_board_canvas.sprite_height,
-                            ..default()
-                        },
+                        game_assets.text_font(game_board_canvas.sprite_height),
                         TextLayout::new_with_justify(JustifyText::Center),
-                        TextColor::from(GREEN),
+                        TextColor(theme.title_color()),
                     ));
                     // debug line to see where is the coordinating system
                     header_box.spawn(());
@@ -108,13 +123,11 @@ pub fn on_enter_main_menu(
                     // middle
                     middle_box.spawn((
                         Text::new(format!("Bestia.dev tutorial\nRust+Bevy+Wasm v{VERSION}")),
-                        TextFont {
-                            font_size: game_board_canvas.sprite_height,
-                            ..default()
-                        },
+                        game_assets.text_font(game_board_canvas.sprite_height),
                         TextLayout::new_with_justify(JustifyText::Center),
-                        TextColor::from(YELLOW),
+                        TextColor(theme.text_color()),
                     ));
+                    spawn_high_score_table(middle_box, &game_board_canvas, &game_assets, &theme, &high_scores, false);
                 });
             }
             // footer
@@ -129,12 +142,16 @@ pub fn on_enter_main_menu(
                     // footer
                     footer_box.spawn((
                         Text::new("Press N to start"),
-                        TextFont {
-                            font_size: game_board_canvas.sprite_height,
-                            ..default()
-                        },
+                        game_assets.text_font(game_board_canvas.sprite_height),
                         TextLayout::new_with_justify(JustifyText::Center),
-                        TextColor::from(RED),
+                        TextColor(theme.hint_color()),
+                    ));
+                    footer_box.spawn((
+                        Text::new(""),
+                        game_assets.text_font(game_board_canvas.sprite_height * 0.7),
+                        TextLayout::new_with_justify(JustifyText::Center),
+                        TextColor(theme.text_color()),
+                        SettingsText {},
                     ));
                 });
             }
@@ -182,7 +199,7 @@ pub fn on_enter_main_menu(
                     Button,
                     ButtonEnum::KeyN,
                     ImageNode {
-                        image: asset_server.load("key_n.png"),
+                        image: game_assets.key_n.clone(),
                         ..default()
                     },
                     Interaction::None,
@@ -208,9 +225,41 @@ pub fn on_exit_dead(mut commands: Commands, entities: Query<Entity, With<Visibil
     }
 }
 
-pub fn handle_main_menu_ui_input(keys: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<AppState>>) {
-    if keys.pressed(KeyCode::KeyN) {
-        next_state.set(AppState::InGame);
+/// the N key or a tap anywhere is the Start intent, the L key opens the level select and the O key the lobby of the online game
+/// the T key selects the next theme
+pub fn handle_main_menu_ui_input(mut intents: EventReader<Intent>, mut next_state: ResMut<NextState<AppState>>, mut game_config: ResMut<GameConfig>, mut selected_theme: ResMut<SelectedTheme>) {
+    for intent in intents.read() {
+        match intent {
+            Intent::Start => next_state.set(AppState::InGame),
+            Intent::Menu(MenuAction::ToggleWalls) => {
+                game_config.rules.walls = match game_config.rules.walls {
+                    Walls::Solid => Walls::Wrap,
+                    Walls::Wrap => Walls::Solid,
+                };
+            }
+            Intent::Menu(MenuAction::ToggleAiAssist) => game_config.ai_assist = !game_config.ai_assist,
+            Intent::Menu(MenuAction::TogglePlayers) => game_config.rules.snake_count = if game_config.rules.snake_count == 1 { 2 } else { 1 },
+            Intent::Menu(MenuAction::SelectLevel) => next_state.set(AppState::LevelSelect),
+            Intent::Menu(MenuAction::PlayOnline) => next_state.set(AppState::Lobby),
+            Intent::Menu(MenuAction::NextTheme) => selected_theme.select_next(),
+            Intent::Turn(..) | Intent::Pause | Intent::Quit | Intent::Replay => {}
+        }
+    }
+}
+
+fn render_settings_text(game_config: Res<GameConfig>, selected_theme: Res<SelectedTheme>, mut settings_text_query: Query<&mut Text, With<SettingsText>>) {
+    if let Ok(mut text) = settings_text_query.single_mut() {
+        let walls = match game_config.rules.walls {
+            Walls::Solid => "solid walls",
+            Walls::Wrap => "no walls",
+        };
+        let ai_assist = if game_config.ai_assist { "on" } else { "off" };
+        let players = if game_config.rules.snake_count == 1 { "one player" } else { "two players, WASD and arrows" };
+        let level = if game_config.level.is_empty() { "open board" } else { game_config.level.as_str() };
+        *text = Text::new(format!(
+            "Press B to toggle: {walls}\nPress I to toggle AI assist: {ai_assist}\nPress 2 to toggle: {players}\nPress L to select the level: {level}\nPress O to play online\nPress T to change the theme: {}",
+            selected_theme.0
+        ));
     }
 }
 
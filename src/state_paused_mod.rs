@@ -0,0 +1,82 @@
+// state_paused_mod.rs
+
+use bevy::color::palettes::css::BLACK;
+use bevy::{color::Color::Srgba, prelude::*};
+use bevy_kira_audio::{AudioInstance, AudioTween};
+
+use crate::game_assets_mod::GameAssets;
+use crate::theme_mod::Theme;
+use crate::web_sys_mod as wsm;
+use crate::{AppState, GameBoardCanvas, InGameState, state_in_game_mod::InstanceHandle};
+
+pub fn add_paused_to_app(app: &mut App) {
+    app.add_systems(OnEnter(InGameState::Paused), on_enter_paused);
+    app.add_systems(OnExit(InGameState::Paused), on_exit_paused);
+    app.add_systems(
+        Update,
+        // the intents to resume are read in handle_movement_input, so the same key press is not read twice
+        // it runs in every state to see the moment the tab is hidden, not only while the game runs
+        auto_pause_on_blur,
+    );
+}
+
+// the game entities remain in the background, only the overlay is StateScoped
+pub fn on_enter_paused(
+    mut commands: Commands,
+    game_board_canvas: Res<GameBoardCanvas>,
+    game_assets: Res<GameAssets>,
+    theme: Res<Theme>,
+    handle: Res<InstanceHandle>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(instance) = audio_instances.get_mut(&handle.0) {
+        instance.pause(AudioTween::default());
+    }
+
+    commands
+        .spawn((
+            StateScoped(InGameState::Paused),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Px(game_board_canvas.board_canvas_width as f32),
+                height: Val::Px(game_board_canvas.board_canvas_height as f32),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(Srgba(BLACK).with_alpha(0.6)),
+        ))
+        .with_children(|overlay| {
+            overlay.spawn((
+                Text::new("Paused"),
+                game_assets.text_font(game_board_canvas.sprite_height * 2.),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.text_color()),
+            ));
+            overlay.spawn((
+                Text::new("Press P or tap to resume"),
+                game_assets.text_font(game_board_canvas.sprite_height),
+                TextLayout::new_with_justify(JustifyText::Center),
+                TextColor(theme.hint_color()),
+            ));
+        });
+}
+
+pub fn on_exit_paused(handle: Res<InstanceHandle>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
+    if let Some(instance) = audio_instances.get_mut(&handle.0) {
+        instance.resume(AudioTween::default());
+    }
+}
+
+/// Pause automatically when the browser tab becomes hidden or loses the focus.
+/// Only at that moment, so the player can resume while the tab is still unfocused.
+/// The online game cannot pause, the server does not wait.
+pub fn auto_pause_on_blur(mut was_blurred: Local<bool>, app_state: Res<State<AppState>>, in_game_state: Option<Res<State<InGameState>>>, mut next_in_game_state: ResMut<NextState<InGameState>>) {
+    let is_blurred = wsm::is_document_hidden_or_blurred();
+    let is_running = in_game_state.is_some_and(|in_game_state| *in_game_state.get() == InGameState::Running);
+    if is_blurred && !*was_blurred && is_running && *app_state.get() != AppState::Online {
+        next_in_game_state.set(InGameState::Paused);
+    }
+    *was_blurred = is_blurred;
+}
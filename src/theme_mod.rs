@@ -0,0 +1,230 @@
+//! src/theme_mod.rs
+//! The themes are in assets/themes/*.theme.ron, the name of the theme is the file name.
+//! A theme names the snake sprite sheet, the font, the colors of the birds, the snakes, the walls and the texts,
+//! and the palette of the background shader in the main menu.
+//! The player selects the theme with T in the main menu, the choice is saved in the browser localStorage.
+
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::game_assets_mod::GameAssets;
+use crate::web_sys_mod as wsm;
+
+/// the web server cannot list a folder, so the themes are listed here in the order of the T key
+pub const THEME_NAMES: [&str; 2] = ["classic", "high_contrast"];
+
+const STORAGE_KEY: &str = "snake_bevy_wasm_theme";
+
+/// The colors are hex like FFD700 or with alpha F8F8FF80, a wrong color is white.
+/// The default is the classic theme, a missing field in the file has the classic value.
+/// The test checks that the default is the same as classic.theme.ron.
+#[derive(Resource, Asset, TypePath, Clone, PartialEq, Debug, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// the file name without .theme.ron
+    #[serde(skip)]
+    pub name: String,
+    /// the image with the tiles head, body, corner and tail, 40px each
+    pub snake_sheet: String,
+    /// None is the default font of Bevy
+    pub font: Option<String>,
+    /// the tint of the snake of every player
+    pub snake_tints: Vec<String>,
+    /// the random colors of the birds, a food kind with a tint does not use them
+    pub bird_colors: Vec<String>,
+    pub wall_color: String,
+    /// the outline of the game board, the dead screen and the touch keys
+    pub board_color: String,
+    /// the header of the main menu and the dead screen
+    pub title_color: String,
+    pub text_color: String,
+    /// the line with the key to press
+    pub hint_color: String,
+    /// the cosine palette a + b * cos(2pi * (c * t + d)) of the background shader
+    pub background_palette: [[f32; 3]; 4],
+    /// how fast the background colors cycle
+    pub background_speed: f32,
+}
+
+/// all the themes, loaded at startup
+#[derive(Resource, Default)]
+pub struct Themes {
+    handles: Vec<Handle<Theme>>,
+}
+
+/// the name of the theme, saved in localStorage
+#[derive(Resource, Debug)]
+pub struct SelectedTheme(pub String);
+
+#[derive(Default)]
+struct ThemeLoader;
+
+#[derive(Debug, thiserror::Error)]
+enum ThemeLoaderError {
+    #[error("Could not read the theme: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the theme: {0}")]
+    Ron(#[from] bevy::asset::ron::error::SpannedError),
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            name: THEME_NAMES[0].to_string(),
+            snake_sheet: "snake_sheet.png".to_string(),
+            font: None,
+            snake_tints: vec!["FFFFFF".to_string(), "87CEFA".to_string()],
+            bird_colors: ["008000", "FF0000", "FFFFFF", "F0FFFF", "FFE4C4", "F0F8FF", "D2691E", "FF7F50", "DC143C"].map(String::from).to_vec(),
+            wall_color: "808080".to_string(),
+            board_color: "FFFFFF".to_string(),
+            title_color: "008000".to_string(),
+            text_color: "FFFF00".to_string(),
+            hint_color: "FF0000".to_string(),
+            background_palette: [[0.5, 0.5, 0.5], [0.5, 0.5, 0.5], [1.0, 1.0, 1.0], [0.263, 0.416, 0.557]],
+            background_speed: 0.4,
+        }
+    }
+}
+
+impl AssetLoader for ThemeLoader {
+    type Asset = Theme;
+    type Settings = ();
+    type Error = ThemeLoaderError;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), load_context: &mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut theme = bevy::asset::ron::de::from_bytes::<Theme>(&bytes)?;
+        let file_name = load_context.path().file_name().map_or(String::new(), |file_name| file_name.to_string_lossy().to_string());
+        theme.name = file_name.trim_end_matches(".theme.ron").to_string();
+        Ok(theme)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+impl Theme {
+    /// the bird color is an index from the game, a theme can have more or less colors
+    pub fn bird_color(&self, color: usize) -> Color {
+        match self.bird_colors.len() {
+            0 => Color::WHITE,
+            len => hex_color(&self.bird_colors[color % len]),
+        }
+    }
+
+    /// a player without a tint in the theme is not tinted
+    pub fn snake_tint(&self, snake_index: usize) -> Color {
+        self.snake_tints.get(snake_index).map_or(Color::WHITE, |tint| hex_color(tint))
+    }
+
+    pub fn wall_color(&self) -> Color {
+        hex_color(&self.wall_color)
+    }
+
+    pub fn board_color(&self) -> Color {
+        hex_color(&self.board_color)
+    }
+
+    pub fn title_color(&self) -> Color {
+        hex_color(&self.title_color)
+    }
+
+    pub fn text_color(&self) -> Color {
+        hex_color(&self.text_color)
+    }
+
+    pub fn hint_color(&self) -> Color {
+        hex_color(&self.hint_color)
+    }
+
+    pub fn background_palette(&self) -> crate::BackgroundPalette {
+        let [a, b, c, d] = self.background_palette.map(Vec3::from_array);
+        crate::BackgroundPalette {
+            a,
+            b,
+            c,
+            d,
+            speed: self.background_speed,
+        }
+    }
+}
+
+fn hex_color(hex: &str) -> Color {
+    Srgba::hex(hex).map_or(Color::WHITE, Color::from)
+}
+
+impl SelectedTheme {
+    /// an unknown name from an older version is the classic theme
+    fn load() -> SelectedTheme {
+        let name = wsm::local_storage_get(STORAGE_KEY).filter(|name| THEME_NAMES.contains(&name.as_str()));
+        SelectedTheme(name.unwrap_or_else(|| THEME_NAMES[0].to_string()))
+    }
+
+    /// the next theme in THEME_NAMES, the choice is saved at once
+    pub fn select_next(&mut self) {
+        let index = THEME_NAMES.iter().position(|name| *name == self.0).map_or(0, |index| (index + 1) % THEME_NAMES.len());
+        self.0 = THEME_NAMES[index].to_string();
+        wsm::local_storage_set(STORAGE_KEY, &self.0);
+    }
+}
+
+impl Themes {
+    /// the handles for the loading state
+    pub fn handles(&self) -> Vec<UntypedHandle> {
+        self.handles.iter().map(|handle| handle.clone().untyped()).collect()
+    }
+}
+
+pub fn add_theme_to_app(app: &mut App) {
+    app.init_asset::<Theme>();
+    app.init_asset_loader::<ThemeLoader>();
+    app.init_resource::<Theme>();
+    app.init_resource::<Themes>();
+    app.insert_resource(SelectedTheme::load());
+    app.add_systems(Startup, load_themes);
+    // PreUpdate runs before the state transition, so OnEnter of the next screen has the new theme
+    app.add_systems(PreUpdate, apply_theme.run_if(resource_changed::<SelectedTheme>.or(on_event::<AssetEvent<Theme>>)));
+}
+
+fn load_themes(asset_server: Res<AssetServer>, mut themes: ResMut<Themes>) {
+    themes.handles = THEME_NAMES.iter().map(|name| asset_server.load(format!("themes/{name}.theme.ron"))).collect();
+}
+
+/// the selected theme becomes the Theme resource, its sprite sheet and font go to GameAssets
+/// a theme that is not loaded yet is applied by the asset event
+fn apply_theme(
+    selected_theme: Res<SelectedTheme>,
+    themes: Res<Themes>,
+    theme_assets: Res<Assets<Theme>>,
+    asset_server: Res<AssetServer>,
+    mut theme: ResMut<Theme>,
+    mut game_assets: ResMut<GameAssets>,
+) {
+    let Some(selected) = themes.handles.iter().filter_map(|handle| theme_assets.get(handle)).find(|loaded| loaded.name == selected_theme.0) else {
+        return;
+    };
+    debug!("theme {selected:?}");
+    *theme = selected.clone();
+    game_assets.set_theme(&theme, &asset_server);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_the_classic_theme_file() {
+        let mut classic: Theme = bevy::asset::ron::de::from_str(include_str!("../web_server_folder/snake_bevy_wasm/assets/themes/classic.theme.ron")).unwrap();
+        classic.name = THEME_NAMES[0].to_string();
+        assert_eq!(classic, Theme::default());
+    }
+
+    #[test]
+    fn high_contrast_theme_parses() {
+        let high_contrast: Theme = bevy::asset::ron::de::from_str(include_str!("../web_server_folder/snake_bevy_wasm/assets/themes/high_contrast.theme.ron")).unwrap();
+        assert_ne!(high_contrast.snake_sheet, Theme::default().snake_sheet);
+    }
+}
@@ -5,9 +5,14 @@
 #![allow(dead_code)]
 
 // region: use
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
 // the macro unwrap! shows the TRUE location where the error has occurred.
 use unwrap::unwrap;
-use wasm_bindgen::JsValue;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
 // use wasm_bindgen_futures::JsFuture;
 use web_sys::console;
 // use web_sys::{Request, RequestInit, Response};
@@ -64,3 +69,131 @@ pub fn get_client_height() -> i32 {
 pub fn get_device_pixel_ratio() -> f32 {
     web_sys::window().expect("There should be a window").device_pixel_ratio() as f32
 }
+
+/// get the value of a query parameter from the url, like ?seed=123
+pub fn get_url_query_param(name: &str) -> Option<String> {
+    let search = window().location().search().ok()?;
+    let url_search_params = web_sys::UrlSearchParams::new_with_str(&search).ok()?;
+    url_search_params.get(name)
+}
+
+/// the url fragment after #, None if it is empty
+pub fn get_url_fragment() -> Option<String> {
+    let hash = window().location().hash().ok()?;
+    let fragment = hash.trim_start_matches('#');
+    (!fragment.is_empty()).then(|| fragment.to_string())
+}
+
+/// change the url fragment after #, the page is not reloaded
+pub fn set_url_fragment(fragment: &str) {
+    if window().location().set_hash(fragment).is_err() {
+        debug_write("Could not set the url fragment");
+    }
+}
+
+/// milliseconds since 1970-01-01 from the javascript clock
+pub fn now_milliseconds() -> f64 {
+    js_sys::Date::now()
+}
+
+/// today UTC date as a number like 20250811
+pub fn get_utc_date_number() -> u64 {
+    let date = js_sys::Date::new_0();
+    date.get_utc_full_year() as u64 * 10000 + (date.get_utc_month() as u64 + 1) * 100 + date.get_utc_date() as u64
+}
+
+/// the browser tab is not visible or the page lost the focus
+pub fn is_document_hidden_or_blurred() -> bool {
+    let document = unwrap!(window().document());
+    document.hidden() || !document.has_focus().unwrap_or(true)
+}
+
+/// today UTC date as text like 2025-08-11
+pub fn get_utc_date_string() -> String {
+    let iso_string: String = js_sys::Date::new_0().to_iso_string().into();
+    iso_string.chars().take(10).collect()
+}
+
+/// read a value from window.localStorage, None if it does not exist or the storage is disabled
+pub fn local_storage_get(key: &str) -> Option<String> {
+    let storage = window().local_storage().ok()??;
+    storage.get_item(key).ok()?
+}
+
+/// write a value into window.localStorage, the error is only logged because the game works also without storage
+pub fn local_storage_set(key: &str, value: &str) {
+    let is_saved = window().local_storage().ok().flatten().is_some_and(|storage| storage.set_item(key, value).is_ok());
+    if !is_saved {
+        debug_write(&format!("Could not write {key} to localStorage"));
+    }
+}
+
+/// WebSocket for the online game, the received text messages wait in the inbox.
+/// The Bevy systems poll the inbox every frame, there are no async callbacks in the game logic.
+pub struct WebSocketConnection {
+    socket: web_sys::WebSocket,
+    inbox: Rc<RefCell<VecDeque<String>>>,
+    is_closed: Rc<Cell<bool>>,
+    // the closures must live as long as the socket
+    _on_message: Closure<dyn FnMut(web_sys::MessageEvent)>,
+    _on_close: Closure<dyn FnMut(web_sys::CloseEvent)>,
+}
+
+impl WebSocketConnection {
+    /// None if the url is not a valid WebSocket url, a refused connection is only closed later
+    pub fn connect(url: &str) -> Option<WebSocketConnection> {
+        let socket = web_sys::WebSocket::new(url).ok()?;
+        let inbox = Rc::new(RefCell::new(VecDeque::new()));
+        let is_closed = Rc::new(Cell::new(false));
+
+        let message_inbox = inbox.clone();
+        let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+            if let Some(text) = event.data().as_string() {
+                message_inbox.borrow_mut().push_back(text);
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        let close_is_closed = is_closed.clone();
+        let on_close = Closure::<dyn FnMut(web_sys::CloseEvent)>::new(move |_event: web_sys::CloseEvent| {
+            close_is_closed.set(true);
+        });
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        Some(WebSocketConnection {
+            socket,
+            inbox,
+            is_closed,
+            _on_message: on_message,
+            _on_close: on_close,
+        })
+    }
+
+    /// the connection is established, before that nothing can be sent
+    pub fn is_open(&self) -> bool {
+        self.socket.ready_state() == web_sys::WebSocket::OPEN
+    }
+
+    /// closed by the server, refused or lost
+    pub fn is_closed(&self) -> bool {
+        self.is_closed.get()
+    }
+
+    /// returns false if the text was not sent
+    pub fn send(&self, text: &str) -> bool {
+        self.is_open() && self.socket.send_with_str(text).is_ok()
+    }
+
+    /// all the messages received since the last call
+    pub fn receive(&self) -> Vec<String> {
+        self.inbox.borrow_mut().drain(..).collect()
+    }
+}
+
+impl Drop for WebSocketConnection {
+    fn drop(&mut self) {
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        let _ = self.socket.close();
+    }
+}
@@ -5,13 +5,19 @@
 
 @group(1) @binding(0) var<uniform> screen_size: vec2<f32>;
 
+// the cosine palette from the theme
+struct BackgroundPalette {
+  a: vec3f,
+  b: vec3f,
+  c: vec3f,
+  d: vec3f,
+  speed: f32,
+}
+
+@group(1) @binding(1) var<uniform> background: BackgroundPalette;
 
 fn palette(t: f32) -> vec3f {
-  const a = vec3f(0.5, 0.5, 0.5);
-  const b = vec3f(0.5, 0.5, 0.5);
-  const c = vec3f(1.0, 1.0, 1.0);
-  const d = vec3f(0.263, 0.416, 0.557);
-  return a + (b * cos(6.28318 * ((c*t) + d)));
+  return background.a + (background.b * cos(6.28318 * ((background.c*t) + background.d)));
 }
 
 @fragment
@@ -29,7 +35,7 @@ fn fragment(@builtin(position) pos: vec4f) -> @location(0) vec4<f32> {
 
     var d = length(uv) * exp(-length(uv0));
 
-    var col = palette(length(uv0) + f32(i) * 0.4 + globals.time * 0.4);
+    var col = palette(length(uv0) + f32(i) * 0.4 + globals.time * background.speed);
 
     d = sin(d * 8.0 + globals.time) / 8.0;
     d = abs(d);